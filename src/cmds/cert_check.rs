@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use clap::Args;
+use openssl::{
+    asn1::Asn1Time,
+    nid::Nid,
+    ssl::{SslConnector, SslMethod, SslVerifyMode},
+    x509::X509Ref,
+};
+
+use crate::{CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState};
+
+use super::utils::connect_timeout_any;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Args)]
+pub struct CertCheckArgs {
+    #[arg(short = 'o', long)]
+    pub host: String,
+    #[arg(short, long, default_value_t = 443)]
+    pub port: u16,
+    // name sent with SNI, the host by default
+    #[arg(short, long)]
+    pub server_name: Option<String>,
+    #[arg(short, long, default_value_t = 7)]
+    pub critical_days: u32,
+    #[arg(short, long, default_value_t = 30)]
+    pub warning_days: u32,
+    #[arg(short, long, default_value_t = 1000)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug)]
+pub struct CertCheck {
+    // common name of the first certificate of the chain to expire
+    subject: String,
+    days_left: i64,
+}
+
+impl CommandStatus<CertCheckArgs> for CertCheck {
+    fn get(command: &CertCheckArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let cert_check = Self::check(
+            &command.host,
+            command.port,
+            command.server_name.as_deref().unwrap_or(&command.host),
+            Duration::from_millis(command.timeout_ms),
+        )?;
+
+        let color = if cert_check.days_left <= command.critical_days as i64 {
+            I3StatusRustColorState::I3StatusRustStateCritical
+        } else if cert_check.days_left <= command.warning_days as i64 {
+            I3StatusRustColorState::I3StatusRustStateWarning
+        } else {
+            I3StatusRustColorState::I3StatusRustStateGood
+        };
+        let (long_line, short_line) = cert_check.i3blocks_print();
+        Ok(Some(I3Display::new(
+            None,
+            long_line,
+            short_line,
+            Some(color),
+        )))
+    }
+}
+
+impl CertCheck {
+    /// Certificates are not verified: expired or self-signed ones still have to be reported
+    fn check(
+        host: &str,
+        port: u16,
+        server_name: &str,
+        timeout: Duration,
+    ) -> Result<Self, I3DisplayError> {
+        let (stream, _) = connect_timeout_any(host, port, timeout)?;
+        stream
+            .set_read_timeout(Some(timeout))
+            .and_then(|_| stream.set_write_timeout(Some(timeout)))
+            .map_err(|e| I3DisplayError::from(format!("can't set socket timeout: {e}")))?;
+
+        let mut connector = SslConnector::builder(SslMethod::tls())
+            .map_err(|e| I3DisplayError::from(format!("can't create tls connector: {e}")))?;
+        connector.set_verify(SslVerifyMode::NONE);
+        let ssl_stream = connector
+            .build()
+            .configure()
+            .map_err(|e| I3DisplayError::from(format!("can't create tls connector: {e}")))?
+            .verify_hostname(false)
+            .connect(server_name, stream)
+            .map_err(|e| {
+                I3DisplayError::from(format!("can't do tls handshake with {host}:{port}: {e}"))
+            })?;
+
+        let chain = ssl_stream.ssl().peer_cert_chain().ok_or_else(|| {
+            I3DisplayError::from(format!("{host}:{port} didn't present any certificate"))
+        })?;
+        let now = Asn1Time::days_from_now(0)
+            .map_err(|e| I3DisplayError::from(format!("can't get current time: {e}")))?;
+        let mut certs = Vec::new();
+        for cert in chain {
+            certs.push(CertCheck {
+                subject: Self::common_name(cert),
+                days_left: Self::days_left(&now, cert)?,
+            });
+        }
+        certs
+            .into_iter()
+            .min_by_key(|x| x.days_left)
+            .ok_or_else(|| {
+                I3DisplayError::from(format!("{host}:{port} didn't present any certificate"))
+            })
+    }
+
+    /// Whole days before the certificate expires, negative once expired
+    fn days_left(now: &Asn1Time, cert: &X509Ref) -> Result<i64, I3DisplayError> {
+        let diff = now
+            .diff(cert.not_after())
+            .map_err(|e| I3DisplayError::from(format!("can't read certificate expiry: {e}")))?;
+        Ok((diff.days as i64 * SECONDS_PER_DAY + diff.secs as i64).div_euclid(SECONDS_PER_DAY))
+    }
+
+    fn common_name(cert: &X509Ref) -> String {
+        cert.subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .and_then(|x| x.data().to_string().ok())
+            .unwrap_or_default()
+    }
+
+    fn i3blocks_print(&self) -> (String, String) {
+        let days = match self.days_left < 0 {
+            true => "expired".to_string(),
+            false => format!("{}d", self.days_left),
+        };
+        match self.subject.is_empty() {
+            true => (days.clone(), days),
+            false => (format!("{} {days}", self.subject), days),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread, time::Duration};
+
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::PKey,
+        rsa::Rsa,
+        ssl::{SslAcceptor, SslMethod},
+        x509::{X509NameBuilder, X509},
+    };
+
+    use super::CertCheck;
+    use crate::cmds::state::unix_timestamp;
+
+    fn self_signed_cert(common_name: &str, days: u32) -> (X509, PKey<openssl::pkey::Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        let name = name.build();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        // an extra hour keeps the days count stable while the test runs
+        let not_after = unix_timestamp() as i64 + days as i64 * 24 * 60 * 60 + 60 * 60;
+        cert.set_not_after(&Asn1Time::from_unix(not_after).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        (cert.build(), key)
+    }
+
+    #[test]
+    fn test_cert_check() {
+        let (cert, key) = self_signed_cert("proxy.dev.lan", 10);
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // the handshake result doesn't matter, the client hangs up right after it
+            let _ = acceptor.accept(stream);
+        });
+
+        let x = CertCheck::check("127.0.0.1", port, "proxy.dev.lan", Duration::from_secs(2));
+        server.join().unwrap();
+        let x = x.unwrap();
+        assert_eq!(x.subject, "proxy.dev.lan");
+        assert_eq!(x.days_left, 10);
+        assert_eq!(
+            x.i3blocks_print(),
+            ("proxy.dev.lan 10d".to_string(), "10d".to_string())
+        );
+
+        let x = CertCheck::check("127.0.0.1", port, "proxy.dev.lan", Duration::from_secs(1));
+        assert!(x.is_err());
+    }
+
+    #[test]
+    fn test_cert_check_print() {
+        let x = CertCheck {
+            subject: String::new(),
+            days_left: -3,
+        };
+        assert_eq!(
+            x.i3blocks_print(),
+            ("expired".to_string(), "expired".to_string())
+        );
+    }
+}
@@ -1,14 +1,17 @@
-use crate::{BytesUnit, CommandStatus, I3Display, I3DisplayError};
+use crate::{BytesStandard, BytesUnit, CommandStatus, I3Display, I3DisplayError};
 use clap::Args;
 use procfs::diskstats;
 use std::{fs, thread::sleep};
 
-use super::utils::set_text_threshold_color;
+use super::utils::{scale_bytes, set_text_threshold_color};
 
 const DISK_CHECK_NUM: u64 = 2;
 const CHECK_INTERVAL_SEC: u64 = 1;
 const DISK_CHECK_INTERVAL: f64 = (DISK_CHECK_NUM * CHECK_INTERVAL_SEC) as f64;
 const PROC_STAT_PATH: &str = "/proc/stat";
+// diskstats sectors are always 512 bytes, whatever the device sector size
+const SECTOR_SIZE: f64 = 512.0;
+const MB: f64 = 1024.0 * 1024.0;
 
 #[derive(Args)]
 pub struct DiskIoArgs {
@@ -18,22 +21,27 @@ pub struct DiskIoArgs {
     pub critical_mb: u64,
     #[arg(short, long, default_value_t = 10)]
     pub warning_mb: u64,
-    #[arg(short, long)]
-    pub unit: Option<BytesUnit>,
+    #[arg(short, long, default_value = "auto")]
+    pub unit: BytesUnit,
+    #[arg(long, default_value = "iec")]
+    pub standard: BytesStandard,
+    #[arg(long, default_value_t = 1)]
+    pub precision: usize,
 }
 
 #[derive(Debug)]
 pub struct DiskIoStats {
-    read_mb: f64,
-    write_mb: f64,
+    read_bytes: f64,
+    write_bytes: f64,
     io_wait: f64,
 }
 
 struct PrettyDiskIoStats {
     read: f64,
     write: f64,
-    read_unit: BytesUnit,
-    write_unit: BytesUnit,
+    read_unit: &'static str,
+    write_unit: &'static str,
+    precision: usize,
     io_wait_percentage: f64,
 }
 
@@ -48,15 +56,25 @@ impl PrettyDiskIoStats {
             warning,
             (warning + critical) / 2.0,
             critical,
-            disk_io_stat.read_mb,
-            Some(format!("{:>5.1}{}/s", self.read, self.read_unit)),
+            disk_io_stat.read_bytes / MB,
+            Some(format!(
+                "{:>5.precision$}{}/s",
+                self.read,
+                self.read_unit,
+                precision = self.precision
+            )),
         );
         let write_value = set_text_threshold_color(
             warning,
             (warning + critical) / 2.0,
             critical,
-            disk_io_stat.write_mb,
-            Some(format!("{:>5.1}{}/s", self.write, self.write_unit)),
+            disk_io_stat.write_bytes / MB,
+            Some(format!(
+                "{:>5.precision$}{}/s",
+                self.write,
+                self.write_unit,
+                precision = self.precision
+            )),
         );
         let iowait_value = set_text_threshold_color(
             5.0,
@@ -74,7 +92,8 @@ impl PrettyDiskIoStats {
 impl CommandStatus<DiskIoArgs> for DiskIoStats {
     fn get(command: &DiskIoArgs) -> Result<Option<I3Display>, I3DisplayError> {
         let io_stats = Self::get_stats(command.device.clone())?;
-        let pretty_output = io_stats.pretty_content(command.unit);
+        let pretty_output =
+            io_stats.pretty_content(command.unit, command.standard, command.precision);
 
         Ok(Some(pretty_output.to_i3blocks_display(
             io_stats,
@@ -118,8 +137,8 @@ impl DiskIoStats {
                 .map_err(|e| I3DisplayError::from(format!("can't get disks stats: {}", e)))?;
             x.iter().for_each(|x| {
                 if x.name == device {
-                    reads.push(x.reads as f64);
-                    writes.push(x.writes as f64);
+                    reads.push(x.sectors_read as f64 * SECTOR_SIZE);
+                    writes.push(x.sectors_written as f64 * SECTOR_SIZE);
                 }
             });
 
@@ -135,68 +154,29 @@ impl DiskIoStats {
         }
 
         Ok(DiskIoStats {
-            read_mb: (reads[1] - reads[0]) / (DISK_CHECK_INTERVAL + 0.5),
-            write_mb: (writes[1] - writes[0]) / (DISK_CHECK_INTERVAL + 0.5),
+            read_bytes: (reads[1] - reads[0]) / (DISK_CHECK_INTERVAL + 0.5),
+            write_bytes: (writes[1] - writes[0]) / (DISK_CHECK_INTERVAL + 0.5),
             io_wait: (iowait[1] - iowait[0]) / DISK_CHECK_INTERVAL,
         })
     }
 
-    fn pretty_content(&self, unit: Option<BytesUnit>) -> PrettyDiskIoStats {
-        let default_unit = match unit {
-            Some(x) => x,
-            None => BytesUnit::Mb,
-        };
-
-        let mut pretty_stats = PrettyDiskIoStats {
-            read: 0.0,
-            write: 0.0,
-            read_unit: default_unit,
-            write_unit: default_unit,
+    fn pretty_content(
+        &self,
+        unit: BytesUnit,
+        standard: BytesStandard,
+        precision: usize,
+    ) -> PrettyDiskIoStats {
+        let (read, read_unit) = scale_bytes(self.read_bytes, unit, standard);
+        let (write, write_unit) = scale_bytes(self.write_bytes, unit, standard);
+
+        PrettyDiskIoStats {
+            read,
+            write,
+            read_unit,
+            write_unit,
+            precision,
             io_wait_percentage: self.io_wait,
-        };
-
-        // thoughput
-        match unit {
-            Some(BytesUnit::Kb) => {
-                pretty_stats.read = self.read_mb * 1024.0;
-                pretty_stats.write = self.write_mb * 1024.0;
-            }
-            Some(BytesUnit::Mb) => {
-                pretty_stats.read = self.read_mb;
-                pretty_stats.write = self.write_mb;
-            }
-            Some(BytesUnit::Gb) => {
-                pretty_stats.read = self.read_mb / 1024.0;
-                pretty_stats.write = self.write_mb / 1024.0;
-            }
-            None => {
-                // auto adaptive read
-                if self.read_mb > 1024.0 {
-                    pretty_stats.read = self.read_mb / 1024.0;
-                    pretty_stats.read_unit = BytesUnit::Gb;
-                } else if self.read_mb < 1.0 {
-                    pretty_stats.read = self.read_mb * 1024.0;
-                    pretty_stats.read_unit = BytesUnit::Kb;
-                } else {
-                    pretty_stats.read = self.read_mb;
-                    pretty_stats.read_unit = BytesUnit::Mb;
-                }
-
-                // auto adaptive write
-                if self.write_mb > 1024.0 {
-                    pretty_stats.write = self.write_mb / 1024.0;
-                    pretty_stats.write_unit = BytesUnit::Gb;
-                } else if self.write_mb < 1.0 {
-                    pretty_stats.write = self.write_mb * 1024.0;
-                    pretty_stats.write_unit = BytesUnit::Kb;
-                } else {
-                    pretty_stats.write = self.write_mb;
-                    pretty_stats.write_unit = BytesUnit::Mb;
-                }
-            }
-        };
-
-        pretty_stats
+        }
     }
 }
 
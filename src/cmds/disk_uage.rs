@@ -1,24 +1,65 @@
-use std::path::Path;
+use std::{
+    ffi::CString,
+    fs,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use clap::{Args, ValueEnum};
+use compound_duration::format_dhms;
+use procfs::process::Process;
 use sysinfo::{DiskExt, System, SystemExt};
 
-use crate::{BytesUnit, CommandStatus, I3Display, I3DisplayError};
+use crate::{
+    BytesStandard, BytesUnit, CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState,
+};
 
-use super::utils::define_threshold_color;
+use super::{
+    state::{unix_timestamp, StateFile},
+    utils::{define_threshold_color, format_bytes, parse_duration},
+};
+
+const SYSFS_BTRFS_PATH: &str = "/sys/fs/btrfs";
+const ZPOOL_BIN: &str = "zpool";
+const BTRFS_BLOCK_GROUPS: [&str; 3] = ["data", "metadata", "system"];
 
 #[derive(Args)]
 pub struct DiskUsageArgs {
-    #[arg(short, long)]
-    pub path: String,
+    #[arg(short, long, required_unless_present = "all")]
+    pub path: Vec<PathBuf>,
+    #[arg(short, long, default_value_t = false, conflicts_with = "path")]
+    pub all: bool,
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        default_value = "tmpfs,devtmpfs,overlay,squashfs"
+    )]
+    pub exclude_fs_types: Vec<String>,
     #[arg(short, long, default_value_t=80, value_parser = clap::value_parser!(u8).range(2..100))]
     pub critical_used_percentage: u8,
     #[arg(short, long, default_value_t=60, value_parser = clap::value_parser!(u8).range(1..100))]
     pub warning_used_percentage: u8,
+    #[arg(long, default_value_t=90, value_parser = clap::value_parser!(u8).range(2..100))]
+    pub critical_inodes_used_percentage: u8,
+    #[arg(long, default_value_t=75, value_parser = clap::value_parser!(u8).range(1..100))]
+    pub warning_inodes_used_percentage: u8,
     #[arg(short, long, default_value = "gb")]
     pub unit: BytesUnit,
+    #[arg(long, default_value = "iec")]
+    pub standard: BytesStandard,
+    #[arg(long, default_value_t = 1)]
+    pub precision: usize,
     #[arg(short, long, default_value = "remaining")]
     pub display: DiskDisplay,
+    #[arg(short, long, default_value = "statvfs")]
+    pub backend: DiskBackend,
+    #[arg(long, default_value = "24h", value_parser = parse_duration)]
+    pub critical_time_to_full: u64,
+    #[arg(long, default_value = "6h", value_parser = parse_duration)]
+    pub time_to_full_window: u64,
 }
 
 #[derive(Clone, Copy, ValueEnum)]
@@ -27,84 +68,549 @@ pub enum DiskDisplay {
     Remaining,
     UsedPercentage,
     RemainingPercentage,
+    InodesUsed,
+    InodesRemaining,
+    InodesUsedPercentage,
+    InodesRemainingPercentage,
+    TimeToFull,
+}
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DiskBackend {
+    Statvfs,
+    Btrfs,
+    Zfs,
 }
+
 #[derive(Debug)]
 pub struct DiskStats {
-    usage_mb: u64,
-    total_mb: u64,
+    mount_point: PathBuf,
+    usage_bytes: u64,
+    total_bytes: u64,
     used_percent: u8,
+    inodes_used: u64,
+    inodes_total: u64,
+    inodes_used_percent: u8,
+    time_to_full: Option<u64>,
 }
 
 impl CommandStatus<DiskUsageArgs> for DiskStats {
     fn get(command: &DiskUsageArgs) -> Result<Option<I3Display>, I3DisplayError> {
-        let disk_stats = Self::get_disk_stats(command.path.clone())?;
-        let lines = disk_stats.i3blocks_print(command.unit, command.display);
-        let color = define_threshold_color(
-            command.warning_used_percentage,
-            (command.critical_used_percentage + command.warning_used_percentage) / 2,
-            command.critical_used_percentage,
-            disk_stats.used_percent as f32,
-        );
+        let mount_points = match command.all {
+            true => Self::get_all_mount_points(&command.exclude_fs_types),
+            false => Self::resolve_mount_points(&command.path)?,
+        };
+        let mut disks_stats = Self::get_disks_stats(mount_points, command.backend, command.all)?;
+        if disks_stats.is_empty() {
+            return Ok(None);
+        }
+        if let DiskDisplay::TimeToFull = command.display {
+            for disk_stats in disks_stats.iter_mut() {
+                disk_stats.update_time_to_full(command.time_to_full_window)?;
+            }
+        }
+
+        let print = |x: &DiskStats| {
+            x.i3blocks_print(
+                command.display,
+                command.unit,
+                command.standard,
+                command.precision,
+            )
+        };
+        let (long_line, short_line) = match disks_stats.as_slice() {
+            [disk_stats] => {
+                let line = print(disk_stats);
+                (line.clone(), line)
+            }
+            _ => (
+                disks_stats
+                    .iter()
+                    .map(|x| format!("{} {}", x.mount_point.display(), print(x)))
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                disks_stats
+                    .iter()
+                    .map(print)
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            ),
+        };
+        let color = disks_stats
+            .iter()
+            .map(|x| x.threshold_color(command))
+            .max()
+            .unwrap_or(I3StatusRustColorState::I3StatusRustStateIdle);
         Ok(Some(I3Display::new(
             None,
-            lines.clone(),
-            lines,
+            long_line,
+            short_line,
             Some(color),
         )))
     }
 }
 
 impl DiskStats {
-    fn get_disk_stats(disk_path: String) -> Result<Self, I3DisplayError> {
-        let disk_as_path = Path::new(&disk_path);
-
+    /// List mount points of real filesystems, skipping excluded filesystem types
+    fn get_all_mount_points(exclude_fs_types: &[String]) -> Vec<PathBuf> {
         let mut sys = System::new();
         sys.refresh_disks_list();
 
+        let mut mount_points: Vec<PathBuf> = Vec::new();
         for d in sys.disks() {
-            if d.mount_point() == disk_as_path {
-                return Ok(DiskStats {
-                    total_mb: d.total_space(),
-                    used_percent: (d.available_space() as f64 / d.total_space() as f64 * 100.0)
-                        as u8,
-                    usage_mb: d.total_space() - d.available_space(),
-                });
+            let fs_type = String::from_utf8_lossy(d.file_system());
+            if exclude_fs_types.iter().any(|x| *x == fs_type)
+                || mount_points.iter().any(|x| x == d.mount_point())
+            {
+                continue;
+            }
+            mount_points.push(d.mount_point().to_path_buf());
+        }
+        mount_points
+    }
+
+    /// Resolve each path to the mount point of the filesystem containing it
+    fn resolve_mount_points(paths: &[PathBuf]) -> Result<Vec<PathBuf>, I3DisplayError> {
+        let mount_infos = Process::myself()
+            .and_then(|x| x.mountinfo())
+            .map_err(|e| I3DisplayError::from(format!("can't read mount points: {e}")))?;
+        let known_mount_points = mount_infos
+            .into_iter()
+            .map(|x| x.mount_point)
+            .collect::<Vec<PathBuf>>();
+
+        let mut mount_points: Vec<PathBuf> = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = fs::canonicalize(path)
+                .map_err(|_| I3DisplayError::from(format!("Disk {} not found", path.display())))?;
+            let mount_point = Self::find_mount_point(&known_mount_points, &path).ok_or(
+                I3DisplayError::from(format!("no mount point found for {}", path.display())),
+            )?;
+            if !mount_points.contains(mount_point) {
+                mount_points.push(mount_point.clone());
             }
         }
+        Ok(mount_points)
+    }
 
-        Err(I3DisplayError::from(format!("Disk {disk_path} not found")))
+    /// Find the deepest mount point containing `path`
+    fn find_mount_point<'a>(mount_points: &'a [PathBuf], path: &Path) -> Option<&'a PathBuf> {
+        mount_points
+            .iter()
+            .filter(|x| path.starts_with(x))
+            .max_by_key(|x| x.components().count())
     }
 
-    fn i3blocks_print(&self, unit: BytesUnit, display: DiskDisplay) -> String {
-        match display {
-            DiskDisplay::Used => match unit {
-                BytesUnit::Kb => format!("{:.1}K", self.usage_mb / 1024),
-                BytesUnit::Mb => format!("{:.1}M", self.usage_mb / 1024 / 1024),
-                BytesUnit::Gb => {
-                    format!("{:.1}G", self.usage_mb / 1024 / 1024 / 1024)
-                }
+    /// Unreadable mount points are skipped with `skip_unreadable` (`--all`), explicitly
+    /// requested ones are errors
+    fn get_disks_stats(
+        mount_points: Vec<PathBuf>,
+        backend: DiskBackend,
+        skip_unreadable: bool,
+    ) -> Result<Vec<Self>, I3DisplayError> {
+        let disks_stats = mount_points
+            .into_iter()
+            .map(|x| Self::get_disk_stats(x, backend));
+        match skip_unreadable {
+            true => Ok(disks_stats.filter_map(|x| x.ok()).collect()),
+            false => disks_stats.collect(),
+        }
+    }
+
+    fn get_disk_stats(mount_point: PathBuf, backend: DiskBackend) -> Result<Self, I3DisplayError> {
+        let stat = Self::statvfs(&mount_point)?;
+        let (total_space, available_space) = match backend {
+            DiskBackend::Statvfs => (stat.f_blocks * stat.f_frsize, stat.f_bavail * stat.f_frsize),
+            DiskBackend::Btrfs => {
+                let device = fs::canonicalize(Self::get_mount_source(&mount_point)?)
+                    .map_err(|e| I3DisplayError::from(format!("can't find btrfs device: {e}")))?;
+                let device_name = device.file_name().unwrap_or_default().to_string_lossy();
+                Self::get_btrfs_space(Path::new(SYSFS_BTRFS_PATH), &device_name)?
+            }
+            DiskBackend::Zfs => {
+                let dataset = Self::get_mount_source(&mount_point)?;
+                let pool = dataset.split('/').next().unwrap_or_default();
+                Self::get_zfs_space(ZPOOL_BIN, pool)?
+            }
+        };
+        let inodes_total = stat.f_files;
+        let inodes_used = inodes_total - stat.f_ffree;
+
+        Ok(DiskStats {
+            mount_point,
+            total_bytes: total_space,
+            used_percent: match total_space {
+                0 => 0,
+                _ => ((total_space - available_space) as f64 / total_space as f64 * 100.0) as u8,
             },
-            DiskDisplay::Remaining => {
-                let total = match unit {
-                    BytesUnit::Kb => self.total_mb / 1024,
-                    BytesUnit::Mb => self.total_mb / 1024 / 1024,
-                    BytesUnit::Gb => self.total_mb / 1024 / 1024 / 1024,
-                };
-                match unit {
-                    BytesUnit::Kb => {
-                        format!("{:.1}K", total - self.usage_mb / 1024)
-                    }
-                    BytesUnit::Mb => format!("{:.1}M", total - self.usage_mb / 1024 / 1024),
-                    BytesUnit::Gb => {
-                        format!("{:.1}G", total - self.usage_mb / 1024 / 1024 / 1024)
-                    }
-                }
+            usage_bytes: total_space - available_space,
+            inodes_used,
+            inodes_total,
+            // some filesystems (btrfs, ...) allocate inodes dynamically and report 0
+            inodes_used_percent: match inodes_total {
+                0 => 0,
+                _ => (inodes_used as f64 / inodes_total as f64 * 100.0) as u8,
+            },
+            time_to_full: None,
+        })
+    }
+
+    /// One history per mount point, `%` then `/` are percent-encoded so distinct paths
+    /// can't share a file
+    fn state_file_name(mount_point: &Path) -> String {
+        format!(
+            "disk-usage{}",
+            mount_point
+                .display()
+                .to_string()
+                .replace('%', "%25")
+                .replace('/', "%2F")
+        )
+    }
+
+    /// Record the used space and estimate when the disk will be full, based on
+    /// the samples from previous runs within the window
+    fn update_time_to_full(&mut self, window: u64) -> Result<(), I3DisplayError> {
+        let state = StateFile::new(&Self::state_file_name(&self.mount_point))?;
+        let samples =
+            Self::add_usage_sample(&state.read(), unix_timestamp(), self.usage_bytes, window);
+        state.write(
+            &samples
+                .iter()
+                .map(|(timestamp, used)| format!("{timestamp} {used}\n"))
+                .collect::<String>(),
+        )?;
+
+        self.time_to_full =
+            Self::estimate_time_to_full(&samples, self.total_bytes - self.usage_bytes);
+        Ok(())
+    }
+
+    /// Parse previous `timestamp used_bytes` samples, drop the ones out of the
+    /// window and append the current one
+    fn add_usage_sample(previous: &str, now: u64, used: u64, window: u64) -> Vec<(u64, u64)> {
+        let mut samples = previous
+            .lines()
+            .filter_map(|line| {
+                let (timestamp, used) = line.split_once(' ')?;
+                Some((timestamp.parse::<u64>().ok()?, used.parse::<u64>().ok()?))
+            })
+            .filter(|(timestamp, _)| *timestamp < now && now - timestamp <= window)
+            .collect::<Vec<(u64, u64)>>();
+        samples.push((now, used));
+        samples
+    }
+
+    /// Seconds until full at the average rate over the samples, `None` if not growing
+    fn estimate_time_to_full(samples: &[(u64, u64)], available: u64) -> Option<u64> {
+        let (first, last) = (samples.first()?, samples.last()?);
+        if last.0 <= first.0 || last.1 <= first.1 {
+            return None;
+        }
+        let rate = (last.1 - first.1) as f64 / (last.0 - first.0) as f64;
+        Some((available as f64 / rate) as u64)
+    }
+
+    fn get_mount_source(mount_point: &Path) -> Result<String, I3DisplayError> {
+        Process::myself()
+            .and_then(|x| x.mountinfo())
+            .map_err(|e| I3DisplayError::from(format!("can't read mount points: {e}")))?
+            .into_iter()
+            .rev()
+            .find(|x| x.mount_point == mount_point)
+            .and_then(|x| x.mount_source)
+            .ok_or(I3DisplayError::from(format!(
+                "no mount source found for {}",
+                mount_point.display()
+            )))
+    }
+
+    /// Get total and available space of a btrfs filesystem from its allocation
+    /// info, taking the data profile (raid1, ...) into account, like `btrfs
+    /// filesystem usage` free estimation
+    fn get_btrfs_space(
+        sysfs_btrfs: &Path,
+        device_name: &str,
+    ) -> Result<(u64, u64), I3DisplayError> {
+        let read_u64 = |path: PathBuf| {
+            fs::read_to_string(&path)
+                .map_err(|e| {
+                    I3DisplayError::from(format!("can't read file {}: {e}", path.display()))
+                })?
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| I3DisplayError::from(format!("can't parse {}: {e}", path.display())))
+        };
+
+        let fs_path = fs::read_dir(sysfs_btrfs)
+            .map_err(|e| {
+                I3DisplayError::from(format!("can't read {}: {e}", sysfs_btrfs.display()))
+            })?
+            .filter_map(|x| x.ok())
+            .map(|x| x.path())
+            .find(|x| x.join("devices").join(device_name).exists())
+            .ok_or(I3DisplayError::from(format!(
+                "btrfs device `{device_name}` not found"
+            )))?;
+
+        // devices size is in 512 bytes sectors
+        let mut devices_size = 0;
+        for device in fs::read_dir(fs_path.join("devices"))
+            .map_err(|e| I3DisplayError::from(format!("can't read btrfs devices: {e}")))?
+            .filter_map(|x| x.ok())
+        {
+            devices_size += read_u64(device.path().join("size"))? * 512;
+        }
+
+        let allocation = fs_path.join("allocation");
+        let mut used = 0;
+        let mut disk_allocated = 0;
+        for block_group in BTRFS_BLOCK_GROUPS {
+            used += read_u64(allocation.join(block_group).join("bytes_used"))?;
+            disk_allocated += read_u64(allocation.join(block_group).join("disk_total"))?;
+        }
+        let data_total = read_u64(allocation.join("data/total_bytes"))?;
+        let data_used = read_u64(allocation.join("data/bytes_used"))?;
+        let data_ratio = match data_total {
+            0 => 1.0,
+            _ => read_u64(allocation.join("data/disk_total"))? as f64 / data_total as f64,
+        };
+
+        let unallocated = devices_size.saturating_sub(disk_allocated);
+        let available =
+            data_total.saturating_sub(data_used) + (unallocated as f64 / data_ratio) as u64;
+        Ok((used + available, available))
+    }
+
+    /// Get size and free space of a ZFS pool from `zpool list`
+    fn get_zfs_space(zpool_bin: &str, pool: &str) -> Result<(u64, u64), I3DisplayError> {
+        let output = Command::new(zpool_bin)
+            .args(["list", "-Hp", "-o", "size,free", pool])
+            .output()
+            .map_err(|e| I3DisplayError::from(format!("can't run {zpool_bin}: {e}")))?;
+        if !output.status.success() {
+            return Err(I3DisplayError::from(format!(
+                "{zpool_bin} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Self::parse_zpool_list(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// `zpool list -Hp -o size,free` output is `size\tfree`
+    fn parse_zpool_list(stdout: &str) -> Result<(u64, u64), I3DisplayError> {
+        let values = stdout
+            .split_whitespace()
+            .map(|x| x.parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|e| I3DisplayError::from(format!("can't parse zpool output: {e}")))?;
+        match values.as_slice() {
+            [size, free] => Ok((*size, *free)),
+            _ => Err(I3DisplayError::from(format!(
+                "unexpected zpool output: `{}`",
+                stdout.trim()
+            ))),
+        }
+    }
+
+    fn statvfs(path: &Path) -> Result<libc::statvfs, I3DisplayError> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| I3DisplayError::from(format!("invalid path {}: {e}", path.display())))?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+        // SAFETY: c_path is a valid NUL terminated string and stat is only read on success
+        match unsafe { libc::statvfs(c_path.as_ptr(), stat.as_mut_ptr()) } {
+            0 => Ok(unsafe { stat.assume_init() }),
+            _ => Err(I3DisplayError::from(format!(
+                "can't statvfs {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            ))),
+        }
+    }
+
+    /// Worst state between used space and used inodes
+    fn threshold_color(&self, command: &DiskUsageArgs) -> I3StatusRustColorState {
+        let bytes_color = define_threshold_color(
+            command.warning_used_percentage,
+            (command.critical_used_percentage + command.warning_used_percentage) / 2,
+            command.critical_used_percentage,
+            self.used_percent as f32,
+        );
+        let inodes_color = define_threshold_color(
+            command.warning_inodes_used_percentage,
+            (command.critical_inodes_used_percentage + command.warning_inodes_used_percentage) / 2,
+            command.critical_inodes_used_percentage,
+            self.inodes_used_percent as f32,
+        );
+        let time_to_full_color = match self.time_to_full {
+            Some(x) if x < command.critical_time_to_full => {
+                I3StatusRustColorState::I3StatusRustStateCritical
             }
+            _ => I3StatusRustColorState::I3StatusRustStateIdle,
+        };
+        bytes_color.max(inodes_color).max(time_to_full_color)
+    }
+
+    fn i3blocks_print(
+        &self,
+        display: DiskDisplay,
+        unit: BytesUnit,
+        standard: BytesStandard,
+        precision: usize,
+    ) -> String {
+        match display {
+            DiskDisplay::Used => format_bytes(self.usage_bytes as f64, unit, standard, precision),
+            DiskDisplay::Remaining => format_bytes(
+                (self.total_bytes - self.usage_bytes) as f64,
+                unit,
+                standard,
+                precision,
+            ),
             DiskDisplay::UsedPercentage => format!("{}%", self.used_percent),
             DiskDisplay::RemainingPercentage => format!("{}%", 100 - self.used_percent),
+            DiskDisplay::InodesUsed => self.inodes_used.to_string(),
+            DiskDisplay::InodesRemaining => (self.inodes_total - self.inodes_used).to_string(),
+            DiskDisplay::InodesUsedPercentage => format!("{}%", self.inodes_used_percent),
+            DiskDisplay::InodesRemainingPercentage => {
+                format!("{}%", 100 - self.inodes_used_percent)
+            }
+            DiskDisplay::TimeToFull => match self.time_to_full {
+                Some(x) => format_dhms(x as usize),
+                None => "∞".to_string(),
+            },
         }
     }
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::{DiskBackend, DiskDisplay, DiskStats};
+    use crate::{cmds::utils::write_files, BytesStandard, BytesUnit};
+
+    #[test]
+    fn test_disk_inodes() {
+        let root_stats =
+            DiskStats::get_disk_stats(PathBuf::from("/"), DiskBackend::Statvfs).unwrap();
+        assert!(root_stats.inodes_used <= root_stats.inodes_total);
+        assert!(
+            DiskStats::get_disk_stats(PathBuf::from("/nonexistent"), DiskBackend::Statvfs).is_err()
+        );
+
+        let disk_stats = DiskStats {
+            mount_point: PathBuf::from("/"),
+            usage_bytes: 0,
+            total_bytes: 0,
+            used_percent: 0,
+            inodes_used: 750,
+            inodes_total: 1000,
+            inodes_used_percent: 75,
+            time_to_full: None,
+        };
+        let print =
+            |display| disk_stats.i3blocks_print(display, BytesUnit::Gb, BytesStandard::Iec, 1);
+        assert_eq!(print(DiskDisplay::InodesUsed), "750");
+        assert_eq!(print(DiskDisplay::InodesRemaining), "250");
+        assert_eq!(print(DiskDisplay::InodesUsedPercentage), "75%");
+        assert_eq!(print(DiskDisplay::InodesRemainingPercentage), "25%");
+    }
+
+    #[test]
+    fn test_disk_mount_points() {
+        let mount_points = ["/", "/home", "/home/user/cache", "/boot"].map(PathBuf::from);
+        let find = |path| DiskStats::find_mount_point(&mount_points, Path::new(path));
+        assert_eq!(find("/etc/fstab"), Some(&mount_points[0]));
+        assert_eq!(find("/home"), Some(&mount_points[1]));
+        assert_eq!(find("/home/user/cache/cargo"), Some(&mount_points[2]));
+        assert_eq!(find("/homework"), Some(&mount_points[0]));
+
+        let resolved = DiskStats::resolve_mount_points(&[PathBuf::from("/"), PathBuf::from("/")]);
+        assert_eq!(resolved.unwrap(), vec![PathBuf::from("/")]);
+        assert!(DiskStats::resolve_mount_points(&[PathBuf::from("/nonexistent")]).is_err());
+
+        let mount_points = vec![PathBuf::from("/"), PathBuf::from("/nonexistent")];
+        let disks_stats =
+            DiskStats::get_disks_stats(mount_points.clone(), DiskBackend::Statvfs, true).unwrap();
+        assert_eq!(
+            disks_stats
+                .iter()
+                .map(|x| &x.mount_point)
+                .collect::<Vec<_>>(),
+            vec![&mount_points[0]]
+        );
+        assert!(DiskStats::get_disks_stats(mount_points, DiskBackend::Statvfs, false).is_err());
+    }
+
+    #[test]
+    fn test_disk_time_to_full() {
+        let previous = "1000 100\n4000 400\ngarbage\n";
+        let samples = DiskStats::add_usage_sample(previous, 5000, 500, 3600);
+        assert_eq!(samples, vec![(4000, 400), (5000, 500)]);
+        // 100 bytes per 1000s
+        assert_eq!(
+            DiskStats::estimate_time_to_full(&samples, 1000),
+            Some(10000)
+        );
+
+        let samples = DiskStats::add_usage_sample(previous, 5000, 300, 3600);
+        assert_eq!(DiskStats::estimate_time_to_full(&samples, 1000), None);
+        let samples = DiskStats::add_usage_sample("", 5000, 300, 3600);
+        assert_eq!(DiskStats::estimate_time_to_full(&samples, 1000), None);
+
+        let name = |x| DiskStats::state_file_name(Path::new(x));
+        assert_eq!(name("/"), "disk-usage%2F");
+        assert_ne!(name("/a_b"), name("/a/b"));
+        assert_ne!(name("/a%2Fb"), name("/a/b"));
+    }
+
+    #[test]
+    fn test_disk_btrfs_space() {
+        let sysfs = std::env::temp_dir().join(format!("btrfs-{}", std::process::id()));
+        let fs_path = sysfs.join("0f8e8a4c-6d7c-4c8e-9f57-1f1a2b3c4d5e");
+        // raid1 over 2 devices of 100GiB, 40GiB of data stored, 50GiB allocated for data
+        let gib = 1024 * 1024 * 1024_u64;
+        let files = [
+            ("devices/sda1/size", 100 * gib / 512),
+            ("devices/sdb1/size", 100 * gib / 512),
+            ("allocation/data/total_bytes", 50 * gib),
+            ("allocation/data/bytes_used", 40 * gib),
+            ("allocation/data/disk_total", 100 * gib),
+            ("allocation/metadata/bytes_used", gib),
+            ("allocation/metadata/disk_total", 4 * gib),
+            ("allocation/system/bytes_used", 0),
+            ("allocation/system/disk_total", 0),
+        ];
+        let files = files.map(|(file, value)| (file, format!("{value}\n")));
+        write_files(
+            &fs_path,
+            &files
+                .iter()
+                .map(|(x, y)| (*x, y.as_str()))
+                .collect::<Vec<_>>(),
+        );
+
+        let (total, available) = DiskStats::get_btrfs_space(&sysfs, "sdb1").unwrap();
+        // 10GiB free in data chunks + 96GiB unallocated / 2 copies
+        assert_eq!(available, 58 * gib);
+        assert_eq!(total, 41 * gib + 58 * gib);
+        assert!(DiskStats::get_btrfs_space(&sysfs, "sdc1").is_err());
+
+        fs::remove_dir_all(sysfs).unwrap();
+    }
+
+    #[test]
+    fn test_disk_zfs_space() {
+        assert_eq!(
+            DiskStats::parse_zpool_list("1000\t400\n").unwrap(),
+            (1000, 400)
+        );
+        assert!(DiskStats::parse_zpool_list("").is_err());
+        assert!(DiskStats::parse_zpool_list("1000\t-\n").is_err());
+        assert!(DiskStats::get_zfs_space("/nonexistent/zpool", "tank").is_err());
+    }
+}
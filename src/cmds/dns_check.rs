@@ -0,0 +1,600 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use clap::{Args, ValueEnum};
+
+use crate::{CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState};
+
+use super::utils::ascending_threshold_color;
+
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+const DNS_PORT: u16 = 53;
+const HEADER_SIZE: usize = 12;
+const CLASS_IN: u16 = 1;
+// recursion desired
+const FLAG_RD: u16 = 0x0100;
+// truncated, the answer has to be asked again over TCP
+const FLAG_TC: u16 = 0x0200;
+const RCODE_NOERROR: u8 = 0;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+// compression pointers can chain, but not forever
+const MAX_NAME_JUMPS: usize = 16;
+
+#[derive(Args)]
+pub struct DnsCheckArgs {
+    #[arg(short, long)]
+    pub name: String,
+    // first nameserver of /etc/resolv.conf by default
+    #[arg(short, long, value_parser = parse_server)]
+    pub server: Option<SocketAddr>,
+    #[arg(short, long, default_value = "a")]
+    pub record_type: DnsRecordType,
+    #[arg(short, long, default_value = "udp")]
+    pub protocol: DnsProtocol,
+    #[arg(short, long, value_delimiter = ',')]
+    pub expected: Vec<String>,
+    #[arg(short, long, default_value_t = 1000)]
+    pub timeout_ms: u64,
+    #[arg(short, long, default_value_t = 100)]
+    pub warning_ms: u64,
+    #[arg(short, long, default_value_t = 500)]
+    pub critical_ms: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum DnsRecordType {
+    A,
+    Aaaa,
+    Cname,
+    Mx,
+    Ns,
+    Txt,
+}
+
+impl DnsRecordType {
+    fn code(&self) -> u16 {
+        match self {
+            DnsRecordType::A => 1,
+            DnsRecordType::Ns => 2,
+            DnsRecordType::Cname => 5,
+            DnsRecordType::Mx => 15,
+            DnsRecordType::Txt => 16,
+            DnsRecordType::Aaaa => 28,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum DnsProtocol {
+    Udp,
+    Tcp,
+}
+
+/// Parse a resolver `ip` or `ip:port`, IPv6 with a port is written `[ip]:port`
+fn parse_server(value: &str) -> Result<SocketAddr, String> {
+    if let Ok(x) = value.parse::<SocketAddr>() {
+        return Ok(x);
+    }
+    value
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, DNS_PORT))
+        .map_err(|_| format!("invalid server `{value}`, expected ip or ip:port"))
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DnsResult {
+    Answers(Vec<String>),
+    NxDomain,
+    ServFail,
+    // any other response code
+    Failure(u8),
+    Timeout,
+}
+
+#[derive(Debug)]
+pub struct DnsCheck {
+    result: DnsResult,
+    time_ms: f64,
+}
+
+impl CommandStatus<DnsCheckArgs> for DnsCheck {
+    fn get(command: &DnsCheckArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let server = match command.server {
+            Some(x) => x,
+            None => Self::default_server(Path::new(RESOLV_CONF_PATH)).ok_or_else(|| {
+                I3DisplayError::from(format!("can't find a nameserver in {RESOLV_CONF_PATH}"))
+            })?,
+        };
+        let dns_check = Self::check(
+            server,
+            &command.name,
+            command.record_type,
+            command.protocol,
+            Duration::from_millis(command.timeout_ms),
+        )?;
+
+        let (long_line, short_line) = dns_check.i3blocks_print(&command.expected);
+        let color = dns_check.threshold_color(command);
+        Ok(Some(I3Display::new(
+            None,
+            long_line,
+            short_line,
+            Some(color),
+        )))
+    }
+}
+
+impl DnsCheck {
+    fn default_server(resolv_conf: &Path) -> Option<SocketAddr> {
+        fs::read_to_string(resolv_conf)
+            .ok()?
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("nameserver"))
+            .find_map(|x| parse_server(x.trim()).ok())
+    }
+
+    /// Truncated UDP answers are asked again over TCP
+    fn check(
+        server: SocketAddr,
+        name: &str,
+        record_type: DnsRecordType,
+        protocol: DnsProtocol,
+        timeout: Duration,
+    ) -> Result<Self, I3DisplayError> {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.subsec_nanos() as u16)
+            .unwrap_or(0)
+            ^ std::process::id() as u16;
+        let query = Self::build_query(id, name, record_type).map_err(I3DisplayError::from)?;
+
+        let start = Instant::now();
+        let mut response = match protocol {
+            DnsProtocol::Udp => Self::query_udp(server, &query, timeout),
+            DnsProtocol::Tcp => Self::query_tcp(server, &query, timeout),
+        };
+        if let (DnsProtocol::Udp, Ok(x)) = (protocol, &response) {
+            if x.len() >= HEADER_SIZE && u16::from_be_bytes([x[2], x[3]]) & FLAG_TC != 0 {
+                response = Self::query_tcp(server, &query, timeout);
+            }
+        }
+        let time_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let result = match response {
+            Ok(x) => Self::parse_response(&x, id, record_type).map_err(|e| {
+                I3DisplayError::from(format!("invalid response from {server}: {e}"))
+            })?,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                DnsResult::Timeout
+            }
+            Err(e) => return Err(I3DisplayError::from(format!("can't query {server}: {e}"))),
+        };
+        Ok(DnsCheck { result, time_ms })
+    }
+
+    fn query_udp(server: SocketAddr, query: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+        let bind_addr: SocketAddr = match server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.connect(server)?;
+        socket.set_read_timeout(Some(timeout))?;
+        socket.send(query)?;
+
+        let mut buffer = [0u8; 4096];
+        loop {
+            let size = socket.recv(&mut buffer)?;
+            // answers to an older query can still be in flight
+            if size >= 2 && buffer[..2] == query[..2] {
+                return Ok(buffer[..size].to_vec());
+            }
+        }
+    }
+
+    /// TCP messages are prefixed by their length
+    fn query_tcp(server: SocketAddr, query: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+        let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut message = (query.len() as u16).to_be_bytes().to_vec();
+        message.extend_from_slice(query);
+        stream.write_all(&message)?;
+
+        let mut length = [0u8; 2];
+        stream.read_exact(&mut length)?;
+        let mut response = vec![0u8; u16::from_be_bytes(length) as usize];
+        stream.read_exact(&mut response)?;
+        Ok(response)
+    }
+
+    fn build_query(id: u16, name: &str, record_type: DnsRecordType) -> Result<Vec<u8>, String> {
+        let mut query = Vec::with_capacity(HEADER_SIZE + name.len() + 6);
+        query.extend_from_slice(&id.to_be_bytes());
+        query.extend_from_slice(&FLAG_RD.to_be_bytes());
+        // 1 question, no answer, authority nor additional records
+        query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.').filter(|x| !x.is_empty()) {
+            if label.len() > 63 {
+                return Err(format!("label `{label}` is longer than 63 characters"));
+            }
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&record_type.code().to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        Ok(query)
+    }
+
+    /// Only answers of the requested type are kept, e.g. not the CNAME leading to an A record
+    fn parse_response(
+        response: &[u8],
+        id: u16,
+        record_type: DnsRecordType,
+    ) -> Result<DnsResult, String> {
+        let read_u16 = |offset: usize| {
+            response
+                .get(offset..offset + 2)
+                .map(|x| u16::from_be_bytes([x[0], x[1]]))
+                .ok_or_else(|| "truncated message".to_string())
+        };
+        if read_u16(0)? != id {
+            return Err("unexpected message id".to_string());
+        }
+        match (read_u16(2)? & 0x000f) as u8 {
+            RCODE_NOERROR => {}
+            RCODE_NXDOMAIN => return Ok(DnsResult::NxDomain),
+            RCODE_SERVFAIL => return Ok(DnsResult::ServFail),
+            rcode => return Ok(DnsResult::Failure(rcode)),
+        }
+        let questions = read_u16(4)?;
+        let answers = read_u16(6)?;
+
+        let mut offset = HEADER_SIZE;
+        for _ in 0..questions {
+            offset = Self::read_name(response, offset)?.1 + 4;
+        }
+        let mut records = Vec::new();
+        for _ in 0..answers {
+            offset = Self::read_name(response, offset)?.1;
+            let answer_type = read_u16(offset)?;
+            let length = read_u16(offset + 8)? as usize;
+            let data_offset = offset + 10;
+            let data = response
+                .get(data_offset..data_offset + length)
+                .ok_or_else(|| "truncated record".to_string())?;
+            offset = data_offset + length;
+            if answer_type != record_type.code() {
+                continue;
+            }
+            records.push(match record_type {
+                DnsRecordType::A => <[u8; 4]>::try_from(data)
+                    .map(|x| Ipv4Addr::from(x).to_string())
+                    .map_err(|_| "invalid A record".to_string())?,
+                DnsRecordType::Aaaa => <[u8; 16]>::try_from(data)
+                    .map(|x| Ipv6Addr::from(x).to_string())
+                    .map_err(|_| "invalid AAAA record".to_string())?,
+                DnsRecordType::Cname | DnsRecordType::Ns => {
+                    Self::read_name(response, data_offset)?.0
+                }
+                // the preference is left out to compare with the expected exchange
+                DnsRecordType::Mx => Self::read_name(response, data_offset + 2)?.0,
+                DnsRecordType::Txt => {
+                    let mut text = String::new();
+                    let mut rest = data;
+                    while let Some((length, tail)) = rest.split_first() {
+                        let chunk = tail
+                            .get(..*length as usize)
+                            .ok_or_else(|| "invalid TXT record".to_string())?;
+                        text.push_str(&String::from_utf8_lossy(chunk));
+                        rest = &tail[chunk.len()..];
+                    }
+                    text
+                }
+            });
+        }
+        Ok(DnsResult::Answers(records))
+    }
+
+    /// Name at `offset` following compression pointers, with the offset right after it
+    fn read_name(message: &[u8], offset: usize) -> Result<(String, usize), String> {
+        let mut labels = Vec::new();
+        let mut position = offset;
+        let mut end = None;
+        let mut jumps = 0;
+        loop {
+            let length = *message
+                .get(position)
+                .ok_or_else(|| "truncated name".to_string())?;
+            match length {
+                0 => break,
+                x if x & 0xc0 == 0xc0 => {
+                    let pointer = message
+                        .get(position + 1)
+                        .map(|y| ((x as usize & 0x3f) << 8) | *y as usize)
+                        .ok_or_else(|| "truncated name".to_string())?;
+                    jumps += 1;
+                    if jumps > MAX_NAME_JUMPS {
+                        return Err("too many compression pointers".to_string());
+                    }
+                    end.get_or_insert(position + 2);
+                    position = pointer;
+                }
+                x => {
+                    let label = message
+                        .get(position + 1..position + 1 + x as usize)
+                        .ok_or_else(|| "truncated name".to_string())?;
+                    labels.push(String::from_utf8_lossy(label).to_string());
+                    position += 1 + x as usize;
+                }
+            }
+        }
+        Ok((labels.join("."), end.unwrap_or(position + 1)))
+    }
+
+    /// Every expected value has to be in the answers, names are case insensitive
+    fn matches(&self, expected: &[String]) -> bool {
+        match &self.result {
+            DnsResult::Answers(answers) => expected.iter().all(|x| {
+                answers
+                    .iter()
+                    .any(|y| y.eq_ignore_ascii_case(x.trim_end_matches('.')))
+            }),
+            _ => false,
+        }
+    }
+
+    /// Critical on errors and mismatches, warning when the name has no record of the
+    /// requested type, then by response time
+    fn threshold_color(&self, command: &DnsCheckArgs) -> I3StatusRustColorState {
+        match &self.result {
+            _ if !self.matches(&command.expected) => {
+                I3StatusRustColorState::I3StatusRustStateCritical
+            }
+            DnsResult::Answers(answers) if answers.is_empty() => {
+                I3StatusRustColorState::I3StatusRustStateWarning
+            }
+            _ => ascending_threshold_color(
+                self.time_ms,
+                command.warning_ms as f64,
+                command.critical_ms as f64,
+            ),
+        }
+    }
+
+    fn i3blocks_print(&self, expected: &[String]) -> (String, String) {
+        let time = format!("{:.0}ms", self.time_ms);
+        match &self.result {
+            DnsResult::Answers(answers) if self.matches(expected) => match answers.is_empty() {
+                true => (format!("no records {time}"), time),
+                false => (format!("{} {time}", answers.join(" ")), time),
+            },
+            DnsResult::Answers(answers) => (
+                format!("mismatch {}", answers.join(" "))
+                    .trim_end()
+                    .to_string(),
+                "mismatch".to_string(),
+            ),
+            DnsResult::NxDomain => ("NXDOMAIN".to_string(), "NXDOMAIN".to_string()),
+            DnsResult::ServFail => ("SERVFAIL".to_string(), "SERVFAIL".to_string()),
+            DnsResult::Failure(rcode) => (format!("RCODE {rcode}"), format!("RCODE {rcode}")),
+            DnsResult::Timeout => ("timeout".to_string(), "timeout".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpListener, UdpSocket},
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
+
+    use clap::Parser;
+
+    use super::{parse_server, DnsCheck, DnsCheckArgs, DnsProtocol, DnsRecordType, DnsResult};
+    use crate::I3StatusRustColorState;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: DnsCheckArgs,
+    }
+
+    /// Answer `query` with records pointing at the question name
+    fn response(query: &[u8], flags: u16, answers: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut response = query[..2].to_vec();
+        response.extend_from_slice(&(0x8180 | flags).to_be_bytes());
+        response.extend_from_slice(&[0, 1]);
+        response.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        response.extend_from_slice(&[0, 0, 0, 0]);
+        response.extend_from_slice(&query[12..]);
+        for (record_type, data) in answers {
+            response.extend_from_slice(&[0xc0, 0x0c]);
+            response.extend_from_slice(&record_type.to_be_bytes());
+            response.extend_from_slice(&[0, 1, 0, 0, 0x0e, 0x10]);
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(data);
+        }
+        response
+    }
+
+    /// Stub resolver answering a single UDP query
+    fn serve_udp(flags: u16, answers: Vec<(u16, Vec<u8>)>) -> (SocketAddr, JoinHandle<UdpSocket>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (size, peer) = socket.recv_from(&mut buffer).unwrap();
+            socket
+                .send_to(&response(&buffer[..size], flags, &answers), peer)
+                .unwrap();
+            socket
+        });
+        (addr, server)
+    }
+
+    /// UDP and TCP sockets on the same free port
+    fn bind_udp_tcp() -> (UdpSocket, TcpListener) {
+        loop {
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            if let Ok(tcp) = TcpListener::bind(udp.local_addr().unwrap()) {
+                return (udp, tcp);
+            }
+        }
+    }
+
+    fn check(server: SocketAddr, record_type: DnsRecordType, protocol: DnsProtocol) -> DnsCheck {
+        DnsCheck::check(
+            server,
+            "intranet.corp",
+            record_type,
+            protocol,
+            Duration::from_secs(1),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_dns_check_udp() {
+        // a CNAME first, then the A record
+        let cname = [&[3][..], b"www", &[0xc0, 0x0c]].concat();
+        let (server, handle) = serve_udp(0, vec![(5, cname), (1, vec![10, 0, 0, 1])]);
+        let x = check(server, DnsRecordType::A, DnsProtocol::Udp);
+        handle.join().unwrap();
+        assert_eq!(x.result, DnsResult::Answers(vec!["10.0.0.1".to_string()]));
+        assert!(x.matches(&["10.0.0.1".to_string()]));
+        assert!(!x.matches(&["10.0.0.2".to_string()]));
+        assert_eq!(
+            x.i3blocks_print(&["10.0.0.2".to_string()]).0,
+            "mismatch 10.0.0.1"
+        );
+
+        let (server, handle) = serve_udp(0, vec![]);
+        let x = check(server, DnsRecordType::A, DnsProtocol::Udp);
+        handle.join().unwrap();
+        assert_eq!(x.result, DnsResult::Answers(Vec::new()));
+        assert_eq!(
+            x.i3blocks_print(&[]).0,
+            format!("no records {:.0}ms", x.time_ms)
+        );
+        let cli = Cli::parse_from(["dns-check", "-n", "intranet.corp"]);
+        assert!(x.threshold_color(&cli.args) == I3StatusRustColorState::I3StatusRustStateWarning);
+        let cli = Cli::parse_from(["dns-check", "-n", "intranet.corp", "-e", "10.0.0.1"]);
+        assert!(x.threshold_color(&cli.args) == I3StatusRustColorState::I3StatusRustStateCritical);
+
+        let (server, handle) = serve_udp(3, vec![]);
+        let x = check(server, DnsRecordType::A, DnsProtocol::Udp);
+        handle.join().unwrap();
+        assert_eq!(x.result, DnsResult::NxDomain);
+        assert_eq!(x.i3blocks_print(&[]).0, "NXDOMAIN");
+
+        let (server, handle) = serve_udp(2, vec![]);
+        let x = check(server, DnsRecordType::A, DnsProtocol::Udp);
+        handle.join().unwrap();
+        assert_eq!(x.result, DnsResult::ServFail);
+
+        // the stub is gone, nobody answers
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let x = DnsCheck::check(
+            socket.local_addr().unwrap(),
+            "intranet.corp",
+            DnsRecordType::A,
+            DnsProtocol::Udp,
+            Duration::from_millis(100),
+        )
+        .unwrap();
+        assert_eq!(x.result, DnsResult::Timeout);
+    }
+
+    #[test]
+    fn test_dns_check_tcp() {
+        // truncated over UDP on the same port, the full answer is only over TCP
+        let (udp, listener) = bind_udp_tcp();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (size, peer) = udp.recv_from(&mut buffer).unwrap();
+            udp.send_to(&response(&buffer[..size], 0x0200, &[]), peer)
+                .unwrap();
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut length = [0u8; 2];
+            stream.read_exact(&mut length).unwrap();
+            let mut query = vec![0u8; u16::from_be_bytes(length) as usize];
+            stream.read_exact(&mut query).unwrap();
+            let txt = [&[5][..], b"hello", &[6], b" world"].concat();
+            let mx = [&[0, 10][..], &[4], b"mail", &[0xc0, 0x0c]].concat();
+            let response = response(&query, 0, &[(16, txt), (15, mx)]);
+            let mut message = (response.len() as u16).to_be_bytes().to_vec();
+            message.extend_from_slice(&response);
+            stream.write_all(&message).unwrap();
+        });
+
+        let x = check(addr, DnsRecordType::Txt, DnsProtocol::Udp);
+        server.join().unwrap();
+        assert_eq!(
+            x.result,
+            DnsResult::Answers(vec!["hello world".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_dns_check_parse() {
+        let query = DnsCheck::build_query(0x1234, "mail.corp.", DnsRecordType::Mx).unwrap();
+        assert_eq!(
+            query[12..],
+            [&[4][..], b"mail", &[4], b"corp", &[0, 0, 15, 0, 1]].concat()
+        );
+        let mx = [&[0, 10][..], &[2], b"mx", &[0xc0, 0x0c]].concat();
+        assert_eq!(
+            DnsCheck::parse_response(&response(&query, 0, &[(15, mx)]), 0x1234, DnsRecordType::Mx),
+            Ok(DnsResult::Answers(vec!["mx.mail.corp".to_string()]))
+        );
+        assert!(DnsCheck::parse_response(&response(&query, 0, &[]), 1, DnsRecordType::Mx).is_err());
+        assert!(DnsCheck::parse_response(&query[..5], 0x1234, DnsRecordType::Mx).is_err());
+        // pointer to itself
+        assert!(DnsCheck::read_name(&[0xc0, 0x00], 0).is_err());
+        assert!(DnsCheck::build_query(1, &"a".repeat(64), DnsRecordType::A).is_err());
+
+        assert_eq!(
+            parse_server("10.0.0.53"),
+            Ok("10.0.0.53:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_server("[::1]:5353"),
+            Ok("[::1]:5353".parse().unwrap())
+        );
+        assert!(parse_server("dns.corp").is_err());
+    }
+
+    #[test]
+    fn test_dns_check_default_server() {
+        let resolv_conf = std::env::temp_dir().join(format!("resolv-{}.conf", std::process::id()));
+        std::fs::write(
+            &resolv_conf,
+            "# generated\nsearch corp\nnameserver fe80::1%eth0\nnameserver 10.0.0.53\n",
+        )
+        .unwrap();
+        assert_eq!(
+            DnsCheck::default_server(&resolv_conf),
+            Some("10.0.0.53:53".parse().unwrap())
+        );
+        std::fs::remove_file(resolv_conf).unwrap();
+    }
+}
@@ -0,0 +1,350 @@
+use std::time::{Duration, Instant};
+
+use clap::{Args, ValueEnum};
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+
+use crate::{CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState};
+
+use super::utils::{ascending_threshold_color, http_client};
+
+#[derive(Args)]
+pub struct HttpCheckArgs {
+    #[arg(short, long)]
+    pub url: String,
+    #[arg(short, long, default_value = "get")]
+    pub method: HttpMethod,
+    #[arg(short, long, default_value = "200-299", value_parser = parse_status_range)]
+    pub status: (u16, u16),
+    #[arg(short, long, value_parser = parse_regex)]
+    pub regex: Option<Regex>,
+    #[arg(short, long)]
+    pub json_pointer: Option<String>,
+    #[arg(long, requires = "json_pointer")]
+    pub json_value: Option<String>,
+    #[arg(short = 'H', long, value_parser = parse_header)]
+    pub header: Vec<(String, String)>,
+    #[arg(short = 'k', long, default_value_t = false)]
+    pub insecure: bool,
+    #[arg(short, long, default_value = "up")]
+    pub availability_text: Option<String>,
+    #[arg(long)]
+    pub unavailability_text: Option<String>,
+    #[arg(short, long, default_value_t = 1000)]
+    pub timeout_ms: u64,
+    #[arg(short, long, default_value_t = false)]
+    pub latency: bool,
+    #[arg(short, long, default_value_t = 300)]
+    pub warning_ms: u64,
+    #[arg(short, long, default_value_t = 1000)]
+    pub critical_ms: u64,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HttpMethod {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+}
+
+impl From<HttpMethod> for Method {
+    fn from(method: HttpMethod) -> Self {
+        match method {
+            HttpMethod::Get => Method::GET,
+            HttpMethod::Head => Method::HEAD,
+            HttpMethod::Post => Method::POST,
+            HttpMethod::Put => Method::PUT,
+            HttpMethod::Delete => Method::DELETE,
+            HttpMethod::Options => Method::OPTIONS,
+        }
+    }
+}
+
+/// Parse a status code `200` or an inclusive range `200-399`
+fn parse_status_range(value: &str) -> Result<(u16, u16), String> {
+    let parse = |x: &str| {
+        x.trim()
+            .parse::<u16>()
+            .map_err(|e| format!("invalid status code `{x}`: {e}"))
+    };
+    let (min, max) = match value.split_once('-') {
+        Some((min, max)) => (parse(min)?, parse(max)?),
+        None => (parse(value)?, parse(value)?),
+    };
+    match min <= max {
+        true => Ok((min, max)),
+        false => Err(format!("invalid status range `{value}`")),
+    }
+}
+
+fn parse_regex(value: &str) -> Result<Regex, String> {
+    Regex::new(value).map_err(|e| e.to_string())
+}
+
+/// Parse a `Name: value` header
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    match value.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("invalid header `{value}`, expected `Name: value`")),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HttpCheckFailure {
+    Unreachable,
+    Status(u16),
+    BodyMismatch,
+}
+
+#[derive(Debug)]
+pub struct HttpCheck {
+    failure: Option<HttpCheckFailure>,
+    latency_ms: Option<f64>,
+}
+
+impl CommandStatus<HttpCheckArgs> for HttpCheck {
+    fn get(command: &HttpCheckArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let http_check = Self::check(command)?;
+        match http_check.failure {
+            None => {
+                if let (true, Some(latency_ms)) = (command.latency, http_check.latency_ms) {
+                    let x = match &command.availability_text {
+                        Some(text) => format!("{text} {latency_ms:.0}ms"),
+                        None => format!("{latency_ms:.0}ms"),
+                    };
+                    let color = ascending_threshold_color(
+                        latency_ms,
+                        command.warning_ms as f64,
+                        command.critical_ms as f64,
+                    );
+                    return Ok(Some(I3Display::new(None, x.clone(), x, Some(color))));
+                }
+                if let Some(x) = command.availability_text.clone() {
+                    return Ok(Some(I3Display::new(None, x.clone(), x, None)));
+                }
+            }
+            Some(failure) => {
+                if let Some(text) = &command.unavailability_text {
+                    // the unexpected status code helps to tell an outage from a misconfiguration
+                    let x = match failure {
+                        HttpCheckFailure::Status(status) => format!("{text} {status}"),
+                        _ => text.clone(),
+                    };
+                    return Ok(Some(I3Display::new(
+                        None,
+                        x,
+                        text.clone(),
+                        Some(I3StatusRustColorState::I3StatusRustStateCritical),
+                    )));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl HttpCheck {
+    fn check(command: &HttpCheckArgs) -> Result<Self, I3DisplayError> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &command.header {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|e| I3DisplayError::from(format!("invalid header {name}: {e}")))?,
+                HeaderValue::from_str(value)
+                    .map_err(|e| I3DisplayError::from(format!("invalid header {name}: {e}")))?,
+            );
+        }
+
+        let client = http_client(Duration::from_millis(command.timeout_ms), command.insecure)?;
+
+        let mut http_check = HttpCheck {
+            failure: None,
+            latency_ms: None,
+        };
+        let start = Instant::now();
+        let body = match client
+            .request(command.method.into(), &command.url)
+            .headers(headers)
+            .send()
+            .and_then(|res| Ok((res.status(), res.text()?)))
+        {
+            Ok((status, body)) => {
+                http_check.latency_ms = Some(start.elapsed().as_secs_f64() * 1000.0);
+                if status.as_u16() < command.status.0 || status.as_u16() > command.status.1 {
+                    http_check.failure = Some(HttpCheckFailure::Status(status.as_u16()));
+                    return Ok(http_check);
+                }
+                body
+            }
+            Err(e) if e.is_builder() => {
+                return Err(I3DisplayError::from(format!(
+                    "invalid url {}: {e}",
+                    command.url
+                )))
+            }
+            Err(_) => {
+                http_check.failure = Some(HttpCheckFailure::Unreachable);
+                return Ok(http_check);
+            }
+        };
+
+        if !Self::body_matches(
+            &body,
+            command.regex.as_ref(),
+            command.json_pointer.as_deref(),
+            command.json_value.as_deref(),
+        ) {
+            http_check.failure = Some(HttpCheckFailure::BodyMismatch);
+        }
+        Ok(http_check)
+    }
+
+    /// Without an expected value, the JSON pointer only has to exist.
+    /// JSON strings are compared unquoted
+    fn body_matches(
+        body: &str,
+        regex: Option<&Regex>,
+        json_pointer: Option<&str>,
+        json_value: Option<&str>,
+    ) -> bool {
+        if let Some(regex) = regex {
+            if !regex.is_match(body) {
+                return false;
+            }
+        }
+        if let Some(json_pointer) = json_pointer {
+            let json = match serde_json::from_str::<serde_json::Value>(body) {
+                Ok(x) => x,
+                Err(_) => return false,
+            };
+            let value = match json.pointer(json_pointer) {
+                Some(x) => x,
+                None => return false,
+            };
+            if let Some(expected) = json_value {
+                let value = match value {
+                    serde_json::Value::String(x) => x.clone(),
+                    x => x.to_string(),
+                };
+                return value == expected;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{parse_header, parse_status_range, HttpCheck, HttpCheckArgs, HttpCheckFailure};
+    use crate::cmds::utils::serve_http;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: HttpCheckArgs,
+    }
+
+    fn check(url: &str, args: &[&str]) -> HttpCheck {
+        let cli = Cli::parse_from(["http-check", "-u", url].iter().chain(args));
+        HttpCheck::check(&cli.args).unwrap()
+    }
+
+    #[test]
+    fn test_http_check() {
+        let (url, server) = serve_http(1, |_| {
+            (
+                "200 OK",
+                "{\"status\":\"ok\",\"checks\":{\"db\":3}}\n".to_string(),
+            )
+        });
+        let x = check(
+            &format!("{url}/health"),
+            &[
+                "-H",
+                "Authorization: Bearer token",
+                "-r",
+                "\"status\":\\s*\"ok\"",
+                "-j",
+                "/checks/db",
+                "--json-value",
+                "3",
+            ],
+        );
+        assert_eq!(x.failure, None);
+        assert!(x.latency_ms.is_some());
+        let request = server.join().unwrap()[0].to_lowercase();
+        assert!(request.starts_with("get /health http/1.1"));
+        assert!(request.contains("authorization: bearer token"));
+
+        let (url, server) = serve_http(1, |_| ("503 Service Unavailable", String::new()));
+        let x = check(&url, &["-m", "head"]);
+        assert_eq!(x.failure, Some(HttpCheckFailure::Status(503)));
+        assert!(server.join().unwrap()[0].starts_with("HEAD / HTTP/1.1"));
+
+        let (url, server) = serve_http(1, |_| ("503 Service Unavailable", String::new()));
+        let x = check(&url, &["-s", "200-503"]);
+        assert_eq!(x.failure, None);
+        server.join().unwrap();
+
+        let (url, server) = serve_http(1, |_| ("200 OK", "{\"status\":\"ko\"}".to_string()));
+        let x = check(&url, &["-j", "/status", "--json-value", "ok"]);
+        assert_eq!(x.failure, Some(HttpCheckFailure::BodyMismatch));
+        server.join().unwrap();
+
+        // nothing listens anymore
+        let x = check(&url, &[]);
+        assert_eq!(x.failure, Some(HttpCheckFailure::Unreachable));
+    }
+
+    #[test]
+    fn test_http_check_body_matches() {
+        let body = r#"{"status":"ok","version":{"major":2}}"#;
+        let regex = regex::Regex::new("status").unwrap();
+        assert!(HttpCheck::body_matches(body, Some(&regex), None, None));
+        assert!(HttpCheck::body_matches(
+            body,
+            None,
+            Some("/version/major"),
+            None
+        ));
+        assert!(HttpCheck::body_matches(
+            body,
+            None,
+            Some("/status"),
+            Some("ok")
+        ));
+        assert!(!HttpCheck::body_matches(body, None, Some("/missing"), None));
+        assert!(!HttpCheck::body_matches(
+            "not json",
+            None,
+            Some("/status"),
+            None
+        ));
+        let regex = regex::Regex::new("^error").unwrap();
+        assert!(!HttpCheck::body_matches(body, Some(&regex), None, None));
+    }
+
+    #[test]
+    fn test_http_check_parse_args() {
+        assert_eq!(parse_status_range("200-399"), Ok((200, 399)));
+        assert_eq!(parse_status_range("204"), Ok((204, 204)));
+        assert!(parse_status_range("399-200").is_err());
+        assert!(parse_status_range("ok").is_err());
+
+        assert_eq!(
+            parse_header("X-Api-Key: a:b"),
+            Ok(("X-Api-Key".to_string(), "a:b".to_string()))
+        );
+        assert!(parse_header("X-Api-Key").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    fmt::Display,
+    io::{self, Read},
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMP_HEADER_SIZE: usize = 8;
+const PAYLOAD: &[u8] = b"i3-status-info";
+
+#[derive(Debug)]
+pub enum IcmpError {
+    // neither a ping socket (net.ipv4.ping_group_range) nor a raw socket (cap_net_raw) is allowed
+    PermissionDenied,
+    NetworkUnreachable,
+    HostUnreachable,
+    Timeout,
+    Io(io::Error),
+}
+
+impl Display for IcmpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IcmpError::PermissionDenied => write!(
+                f,
+                "can't open an ICMP socket: add your group to net.ipv4.ping_group_range or set cap_net_raw"
+            ),
+            IcmpError::NetworkUnreachable => write!(f, "Network unreachable"),
+            IcmpError::HostUnreachable => write!(f, "Host unreachable"),
+            IcmpError::Timeout => write!(f, "Timeout"),
+            IcmpError::Io(e) => write!(f, "ICMP error: {e}"),
+        }
+    }
+}
+
+impl From<io::Error> for IcmpError {
+    fn from(e: io::Error) -> Self {
+        match e.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EPERM) => return IcmpError::PermissionDenied,
+            Some(libc::ENETUNREACH) => return IcmpError::NetworkUnreachable,
+            Some(libc::EHOSTUNREACH) | Some(libc::ECONNREFUSED) => {
+                return IcmpError::HostUnreachable
+            }
+            _ => {}
+        }
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => IcmpError::Timeout,
+            _ => IcmpError::Io(e),
+        }
+    }
+}
+
+impl IcmpError {
+    /// Errors meaning the probe is lost rather than the check is broken
+    pub fn is_unreachable(&self) -> bool {
+        matches!(
+            self,
+            IcmpError::NetworkUnreachable | IcmpError::HostUnreachable | IcmpError::Timeout
+        )
+    }
+}
+
+/// ICMP echo socket, unprivileged ping socket when allowed, raw socket otherwise
+pub struct IcmpSocket {
+    socket: Socket,
+    ip: IpAddr,
+    raw: bool,
+    ident: u16,
+}
+
+impl IcmpSocket {
+    pub fn new(ip: IpAddr) -> Result<Self, IcmpError> {
+        let (domain, protocol) = match ip {
+            IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+            IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+        };
+        let (socket, raw) = match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+            Ok(x) => (x, false),
+            Err(e) => match IcmpError::from(e) {
+                IcmpError::PermissionDenied => {
+                    (Socket::new(domain, Type::RAW, Some(protocol))?, true)
+                }
+                e => return Err(e),
+            },
+        };
+        socket.connect(&SocketAddr::new(ip, 0).into())?;
+
+        Ok(Self {
+            socket,
+            ip,
+            raw,
+            // ping sockets get their identifier from the kernel
+            ident: std::process::id() as u16,
+        })
+    }
+
+    /// Send an echo request and wait for its reply, returns the round trip time
+    pub fn ping(&self, seq: u16, timeout: Duration) -> Result<Duration, IcmpError> {
+        let start = Instant::now();
+        self.socket.send(&self.echo_request(seq))?;
+
+        let mut buffer = [0u8; 1500];
+        loop {
+            let remaining = timeout
+                .checked_sub(start.elapsed())
+                .filter(|x| !x.is_zero())
+                .ok_or(IcmpError::Timeout)?;
+            self.socket.set_read_timeout(Some(remaining))?;
+            let size = (&self.socket).read(&mut buffer)?;
+            if self.is_echo_reply(&buffer[..size], seq) {
+                return Ok(start.elapsed());
+            }
+        }
+    }
+
+    fn echo_request(&self, seq: u16) -> Vec<u8> {
+        let request_type = match self.ip {
+            IpAddr::V4(_) => ICMPV4_ECHO_REQUEST,
+            IpAddr::V6(_) => ICMPV6_ECHO_REQUEST,
+        };
+        let mut packet = vec![request_type, 0, 0, 0];
+        packet.extend_from_slice(&self.ident.to_be_bytes());
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(PAYLOAD);
+        // the kernel computes ICMPv6 checksums itself
+        if self.ip.is_ipv4() {
+            let checksum = checksum(&packet);
+            packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+        }
+        packet
+    }
+
+    fn is_echo_reply(&self, packet: &[u8], seq: u16) -> bool {
+        let (reply_type, packet) = match self.ip {
+            // raw IPv4 sockets also receive the IP header
+            IpAddr::V4(_) if self.raw => match packet.first() {
+                Some(x) => (
+                    ICMPV4_ECHO_REPLY,
+                    &packet[((x & 0x0f) as usize * 4).min(packet.len())..],
+                ),
+                None => return false,
+            },
+            IpAddr::V4(_) => (ICMPV4_ECHO_REPLY, packet),
+            IpAddr::V6(_) => (ICMPV6_ECHO_REPLY, packet),
+        };
+        if packet.len() < ICMP_HEADER_SIZE || packet[0] != reply_type {
+            return false;
+        }
+        // ping sockets only get their own replies, with an identifier set by the kernel
+        if self.raw && packet[4..6] != self.ident.to_be_bytes() {
+            return false;
+        }
+        packet[6..8] == seq.to_be_bytes()
+    }
+}
+
+/// Internet checksum (RFC 1071)
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|x| match x {
+            [a, b] => u16::from_be_bytes([*a, *b]) as u32,
+            [a] => u16::from_be_bytes([*a, 0]) as u32,
+            _ => 0,
+        })
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use super::{checksum, IcmpError, IcmpSocket};
+
+    #[test]
+    fn test_icmp_checksum() {
+        // example from RFC 1071
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&data), !0xddf2);
+
+        let mut packet = vec![8, 0, 0, 0, 0x12, 0x34, 0, 1, b'x'];
+        let x = checksum(&packet);
+        packet[2..4].copy_from_slice(&x.to_be_bytes());
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn test_icmp_error_classification() {
+        let error = |code| IcmpError::from(io::Error::from_raw_os_error(code));
+        assert!(matches!(error(libc::EACCES), IcmpError::PermissionDenied));
+        assert!(matches!(error(libc::EPERM), IcmpError::PermissionDenied));
+        assert!(matches!(
+            error(libc::ENETUNREACH),
+            IcmpError::NetworkUnreachable
+        ));
+        assert!(matches!(
+            error(libc::EHOSTUNREACH),
+            IcmpError::HostUnreachable
+        ));
+        assert!(matches!(error(libc::EAGAIN), IcmpError::Timeout));
+        assert!(matches!(error(libc::EBADF), IcmpError::Io(_)));
+        assert!(error(libc::EAGAIN).is_unreachable());
+        assert!(!error(libc::EACCES).is_unreachable());
+    }
+
+    #[test]
+    #[ignore = "needs ICMP sockets"]
+    fn test_icmp_echo_reply() {
+        let socket = IcmpSocket::new(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let reply = |ident: u16, seq: u16| {
+            let mut packet = vec![0, 0, 0, 0];
+            packet.extend_from_slice(&ident.to_be_bytes());
+            packet.extend_from_slice(&seq.to_be_bytes());
+            if socket.raw {
+                // minimal IPv4 header
+                let mut header = vec![0x45];
+                header.resize(20, 0);
+                header.extend(packet);
+                return header;
+            }
+            packet
+        };
+        assert!(socket.is_echo_reply(&reply(socket.ident, 3), 3));
+        assert!(!socket.is_echo_reply(&reply(socket.ident, 4), 3));
+        assert!(!socket.is_echo_reply(&[0, 0], 3));
+
+        let rtt = socket.ping(1, Duration::from_secs(1)).unwrap();
+        assert!(rtt < Duration::from_secs(1));
+    }
+}
@@ -1,30 +1,95 @@
-use std::{net::IpAddr, time::Duration};
+use std::{
+    net::{IpAddr, ToSocketAddrs},
+    time::Duration,
+};
 
-use clap::Args;
+use clap::{Args, ValueEnum};
 
-use crate::{CommandStatus, I3Display, I3DisplayError};
+use crate::{CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState};
+
+use super::{
+    icmp::IcmpSocket,
+    utils::{
+        ascending_threshold_color, check_concurrently, parse_key_value, targets_status_display,
+    },
+};
 
 #[derive(Args)]
 pub struct IcmpCheckArgs {
-    #[arg(short, long)]
-    pub ip: IpAddr,
+    #[arg(
+        short = 'o',
+        long,
+        visible_alias = "ip",
+        short_alias = 'i',
+        required_unless_present = "target",
+        conflicts_with = "target"
+    )]
+    pub host: Option<String>,
+    #[arg(long, value_parser = parse_key_value)]
+    pub target: Vec<(String, String)>,
     #[arg(short, long, default_value = "up")]
     pub availability_text: Option<String>,
     #[arg(short, long)]
     pub unavailability_text: Option<String>,
     #[arg(short, long, default_value = "100")]
     pub timeout_ms: u64,
+    #[arg(short = 'n', long, default_value_t = 3, value_parser = clap::value_parser!(u16).range(1..))]
+    pub count: u16,
+    // targets are only shown up or down
+    #[arg(short, long, value_delimiter = ',', conflicts_with = "target")]
+    pub display: Vec<IcmpDisplay>,
+    #[arg(long, default_value_t = 100)]
+    pub warning_rtt_ms: u64,
+    #[arg(long, default_value_t = 300)]
+    pub critical_rtt_ms: u64,
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(..=100))]
+    pub warning_loss: u8,
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u8).range(..=100))]
+    pub critical_loss: u8,
 }
 
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum IcmpDisplay {
+    Rtt,
+    Jitter,
+    Loss,
+}
+
+#[derive(Debug)]
 pub struct IcmpCheck {
-    available: bool,
+    sent: u16,
+    // round trip times of the answered probes
+    rtts_ms: Vec<f64>,
 }
 
 impl CommandStatus<IcmpCheckArgs> for IcmpCheck {
     fn get(command: &IcmpCheckArgs) -> Result<Option<I3Display>, I3DisplayError> {
-        let icmp_check = Self::check(command.ip, command.timeout_ms)?;
-        match icmp_check.available {
+        if !command.target.is_empty() {
+            // a target failing to be checked is down, the others are still shown
+            let results = check_concurrently(&command.target, |(label, host)| {
+                let icmp_check = Self::check(host, command.count, command.timeout_ms);
+                (label.clone(), icmp_check.is_ok_and(|x| x.available()))
+            });
+            return Ok(Some(targets_status_display(&results)));
+        }
+
+        let Some(host) = &command.host else {
+            return Err(I3DisplayError::from(
+                "a host or targets are required".to_string(),
+            ));
+        };
+        let icmp_check = Self::check(host, command.count, command.timeout_ms)?;
+        match icmp_check.available() {
             true => {
+                if !command.display.is_empty() {
+                    let fields = icmp_check.i3blocks_print(&command.display);
+                    let x = match &command.availability_text {
+                        Some(text) => format!("{text} {fields}"),
+                        None => fields,
+                    };
+                    let color = icmp_check.threshold_color(command);
+                    return Ok(Some(I3Display::new(None, x.clone(), x, Some(color))));
+                }
                 if command.availability_text.is_some() {
                     let x = command.availability_text.clone().unwrap();
                     return Ok(Some(I3Display::new(None, x.clone(), x, None)));
@@ -42,33 +107,163 @@ impl CommandStatus<IcmpCheckArgs> for IcmpCheck {
 }
 
 impl IcmpCheck {
-    fn check(host: IpAddr, timeout_ms: u64) -> Result<Self, I3DisplayError> {
-        let mut icmp_check = IcmpCheck { available: false };
-        match ping::ping(
-            host,
-            Some(Duration::from_millis(timeout_ms)),
-            None,
-            None,
-            None,
-            None,
-        ) {
-            Ok(_) => icmp_check.available = true,
-            Err(e) => {
-                if !e.to_string().contains("Resource temporarily unavailable")
-                    && !e.to_string().contains("Network is unreachable")
-                    && !e.to_string().contains("internal error")
-                {
-                    return Err(I3DisplayError::from(e.to_string()));
-                }
+    /// Send `count` probes one after the other, an unresolvable host loses them all
+    fn check(host: &str, count: u16, timeout_ms: u64) -> Result<Self, I3DisplayError> {
+        let mut icmp_check = IcmpCheck {
+            sent: count,
+            rtts_ms: Vec::new(),
+        };
+        let ip = match Self::resolve(host) {
+            Some(x) => x,
+            None => return Ok(icmp_check),
+        };
+        let socket = match IcmpSocket::new(ip) {
+            Ok(x) => x,
+            Err(e) if e.is_unreachable() => return Ok(icmp_check),
+            Err(e) => return Err(I3DisplayError::from(e.to_string())),
+        };
+        for seq in 0..count {
+            match socket.ping(seq, Duration::from_millis(timeout_ms)) {
+                Ok(rtt) => icmp_check.rtts_ms.push(rtt.as_secs_f64() * 1000.0),
+                Err(e) if e.is_unreachable() => {}
+                Err(e) => return Err(I3DisplayError::from(e.to_string())),
             }
         }
         Ok(icmp_check)
     }
+
+    fn resolve(host: &str) -> Option<IpAddr> {
+        (host, 0)
+            .to_socket_addrs()
+            .ok()?
+            .next()
+            .map(|addr| addr.ip())
+    }
+
+    fn available(&self) -> bool {
+        !self.rtts_ms.is_empty()
+    }
+
+    fn average_rtt_ms(&self) -> Option<f64> {
+        match self.rtts_ms.is_empty() {
+            true => None,
+            false => Some(self.rtts_ms.iter().sum::<f64>() / self.rtts_ms.len() as f64),
+        }
+    }
+
+    /// Mean difference between consecutive round trip times, needs 2 answers
+    fn jitter_ms(&self) -> Option<f64> {
+        if self.rtts_ms.len() < 2 {
+            return None;
+        }
+        let deltas = self.rtts_ms.windows(2).map(|x| (x[1] - x[0]).abs());
+        Some(deltas.sum::<f64>() / (self.rtts_ms.len() - 1) as f64)
+    }
+
+    fn loss_percent(&self) -> f64 {
+        match self.sent {
+            0 => 0.0,
+            sent => (sent as usize - self.rtts_ms.len()) as f64 * 100.0 / sent as f64,
+        }
+    }
+
+    /// Worst of the round trip time and packet loss colors
+    fn threshold_color(&self, command: &IcmpCheckArgs) -> I3StatusRustColorState {
+        let rtt_color = ascending_threshold_color(
+            self.average_rtt_ms().unwrap_or(0.0),
+            command.warning_rtt_ms as f64,
+            command.critical_rtt_ms as f64,
+        );
+        let loss_color = ascending_threshold_color(
+            self.loss_percent(),
+            command.warning_loss as f64,
+            command.critical_loss as f64,
+        );
+        rtt_color.max(loss_color)
+    }
+
+    fn i3blocks_print(&self, display: &[IcmpDisplay]) -> String {
+        display
+            .iter()
+            .filter_map(|x| match x {
+                IcmpDisplay::Rtt => self.average_rtt_ms().map(|x| format!("{x:.0}ms")),
+                IcmpDisplay::Jitter => self.jitter_ms().map(|x| format!("±{x:.1}ms")),
+                IcmpDisplay::Loss => Some(format!("{:.0}%", self.loss_percent())),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use clap::Parser;
+
+    use super::{IcmpCheck, IcmpCheckArgs, IcmpDisplay};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: IcmpCheckArgs,
+    }
+
+    #[test]
+    fn test_icmp_check_targets_args() {
+        let cli = Cli::try_parse_from(["icmp-check", "--target", "vpn=10.0.0.1"]).unwrap();
+        assert_eq!(
+            cli.args.target,
+            vec![("vpn".to_string(), "10.0.0.1".to_string())]
+        );
+        assert!(
+            Cli::try_parse_from(["icmp-check", "--target", "vpn=10.0.0.1", "-d", "rtt"]).is_err()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs ICMP sockets"]
+    fn test_icmp_check_connectivity() {
+        let x = IcmpCheck::check("127.0.0.1", 2, 1000).unwrap();
+        assert!(x.available());
+        assert_eq!(x.loss_percent(), 0.0);
+    }
+
+    #[test]
+    fn test_icmp_check_resolve() {
+        assert!(IcmpCheck::resolve("localhost").is_some());
+        assert_eq!(
+            IcmpCheck::resolve("10.0.0.1").map(|x| x.to_string()),
+            Some("10.0.0.1".to_string())
+        );
+        assert!(IcmpCheck::resolve("invalid.invalid").is_none());
+
+        let x = IcmpCheck::check("invalid.invalid", 3, 100).unwrap();
+        assert!(!x.available());
+        assert_eq!(x.loss_percent(), 100.0);
+    }
 
     #[test]
-    fn test_icmp_check_connectivity() {}
+    fn test_icmp_check_stats() {
+        let x = IcmpCheck {
+            sent: 4,
+            rtts_ms: vec![10.0, 14.0, 12.0],
+        };
+        assert!(x.available());
+        assert_eq!(x.average_rtt_ms(), Some(12.0));
+        assert_eq!(x.jitter_ms(), Some(3.0));
+        assert_eq!(x.loss_percent(), 25.0);
+        assert_eq!(
+            x.i3blocks_print(&[IcmpDisplay::Rtt, IcmpDisplay::Jitter, IcmpDisplay::Loss]),
+            "12ms ±3.0ms 25%"
+        );
+
+        let x = IcmpCheck {
+            sent: 1,
+            rtts_ms: vec![8.0],
+        };
+        assert_eq!(x.jitter_ms(), None);
+        assert_eq!(
+            x.i3blocks_print(&[IcmpDisplay::Jitter, IcmpDisplay::Loss]),
+            "0%"
+        );
+    }
 }
@@ -0,0 +1,374 @@
+use std::{
+    ffi::CStr,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use clap::{Args, ValueEnum};
+
+use crate::{CommandStatus, I3Display, I3DisplayError};
+
+use super::{
+    state::{unix_timestamp, StateFile},
+    utils::{http_client, parse_duration},
+};
+
+const STATE_FILE_NAME: &str = "public-ip";
+
+#[derive(Args)]
+pub struct IpArgs {
+    // all interfaces but loopback by default
+    #[arg(short, long, value_delimiter = ',')]
+    pub interface: Vec<String>,
+    #[arg(short, long, default_value = "all")]
+    pub family: IpFamily,
+    #[arg(short, long, default_value_t = false)]
+    pub public: bool,
+    // endpoint answering the caller IP address as plain text
+    #[arg(long, default_value = "https://api.ipify.org")]
+    pub public_url: String,
+    #[arg(long, default_value = "15m", value_parser = parse_duration)]
+    pub public_cache: u64,
+    #[arg(short, long, default_value_t = 1000)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum IpFamily {
+    All,
+    V4,
+    V6,
+}
+
+#[derive(Debug)]
+pub struct IpStatus {
+    addresses: Vec<(String, IpAddr)>,
+    // None when the public address is requested but can't be found
+    public: Option<Option<IpAddr>>,
+}
+
+impl CommandStatus<IpArgs> for IpStatus {
+    fn get(command: &IpArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let addresses =
+            Self::filter_addresses(Self::get_addresses()?, &command.interface, command.family);
+        // local addresses are still shown when the public one can't be found
+        let public = match command.public {
+            true => Some(
+                StateFile::new(STATE_FILE_NAME)
+                    .and_then(|state| {
+                        Self::get_public_ip(
+                            &state,
+                            &command.public_url,
+                            command.public_cache,
+                            Duration::from_millis(command.timeout_ms),
+                        )
+                    })
+                    .ok()
+                    .flatten(),
+            ),
+            false => None,
+        };
+
+        let ip_status = IpStatus { addresses, public };
+        if ip_status.addresses.is_empty() && ip_status.public.is_none() {
+            return Ok(None);
+        }
+        let (long_line, short_line) = ip_status.i3blocks_print();
+        Ok(Some(I3Display::new(None, long_line, short_line, None)))
+    }
+}
+
+impl IpStatus {
+    /// Addresses of all interfaces, from `getifaddrs`
+    fn get_addresses() -> Result<Vec<(String, IpAddr)>, I3DisplayError> {
+        let mut ifaddrs = std::ptr::null_mut();
+        // SAFETY: ifaddrs is only read on success and freed once below
+        if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+            return Err(I3DisplayError::from(format!(
+                "can't get interfaces addresses: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+
+        let mut addresses = Vec::new();
+        let mut current = ifaddrs;
+        while !current.is_null() {
+            // SAFETY: entries of the list and their addresses stay valid until freeifaddrs,
+            // the address type is given by its family
+            let (name, address) = unsafe {
+                let ifaddr = &*current;
+                current = ifaddr.ifa_next;
+                if ifaddr.ifa_addr.is_null() {
+                    continue;
+                }
+                let address = match (*ifaddr.ifa_addr).sa_family as i32 {
+                    libc::AF_INET => {
+                        let x = &*(ifaddr.ifa_addr as *const libc::sockaddr_in);
+                        IpAddr::V4(Ipv4Addr::from(u32::from_be(x.sin_addr.s_addr)))
+                    }
+                    libc::AF_INET6 => {
+                        let x = &*(ifaddr.ifa_addr as *const libc::sockaddr_in6);
+                        IpAddr::V6(Ipv6Addr::from(x.sin6_addr.s6_addr))
+                    }
+                    _ => continue,
+                };
+                (CStr::from_ptr(ifaddr.ifa_name).to_string_lossy(), address)
+            };
+            addresses.push((name.to_string(), address));
+        }
+        // SAFETY: ifaddrs comes from a successful getifaddrs and isn't used afterwards
+        unsafe { libc::freeifaddrs(ifaddrs) };
+        Ok(addresses)
+    }
+
+    /// IPv6 link-local addresses are left out, they are on every interface
+    fn filter_addresses(
+        addresses: Vec<(String, IpAddr)>,
+        interfaces: &[String],
+        family: IpFamily,
+    ) -> Vec<(String, IpAddr)> {
+        let mut addresses = addresses
+            .into_iter()
+            .filter(|(name, address)| match interfaces.is_empty() {
+                true => !address.is_loopback(),
+                false => interfaces.contains(name),
+            })
+            .filter(|(_, address)| match address {
+                IpAddr::V4(_) => family != IpFamily::V6,
+                IpAddr::V6(x) => family != IpFamily::V4 && (x.segments()[0] & 0xffc0) != 0xfe80,
+            })
+            .collect::<Vec<_>>();
+        // keep the requested interfaces order
+        addresses.sort_by_key(|(name, _)| interfaces.iter().position(|x| x == name));
+        addresses
+    }
+
+    /// Public address from the echo endpoint, asked at most once every `max_age`
+    /// seconds, failed lookups included. The previous address is kept when the endpoint
+    /// can't be reached, `None` without one
+    fn get_public_ip(
+        state: &StateFile,
+        url: &str,
+        max_age: u64,
+        timeout: Duration,
+    ) -> Result<Option<IpAddr>, I3DisplayError> {
+        let cached = Self::parse_cache(&state.read(), url);
+        let now = unix_timestamp();
+        if let Some((timestamp, address)) = cached {
+            if now.saturating_sub(timestamp) < max_age {
+                return Ok(address);
+            }
+        }
+
+        let address = Self::fetch_public_ip(url, timeout)
+            .ok()
+            .or(cached.and_then(|(_, address)| address));
+        let cached_address = address.map_or("-".to_string(), |x| x.to_string());
+        state.write(&format!("{now} {cached_address} {url}\n"))?;
+        Ok(address)
+    }
+
+    fn fetch_public_ip(url: &str, timeout: Duration) -> Result<IpAddr, I3DisplayError> {
+        let body = http_client(timeout, false)?
+            .get(url)
+            .send()
+            .and_then(|res| res.error_for_status())
+            .and_then(|res| res.text())
+            .map_err(|e| I3DisplayError::from(format!("can't get public ip from {url}: {e}")))?;
+        body.trim().parse::<IpAddr>().map_err(|_| {
+            I3DisplayError::from(format!("unexpected answer from {url}: `{}`", body.trim()))
+        })
+    }
+
+    /// Cache is `timestamp address url` with the time of the last lookup, the address
+    /// is `-` when none was ever found. An address from another endpoint is ignored
+    fn parse_cache(content: &str, url: &str) -> Option<(u64, Option<IpAddr>)> {
+        let mut fields = content.trim().splitn(3, ' ');
+        let timestamp = fields.next()?.parse().ok()?;
+        let address = match fields.next()? {
+            "-" => None,
+            x => Some(x.parse().ok()?),
+        };
+        (fields.next()? == url).then_some((timestamp, address))
+    }
+
+    fn i3blocks_print(&self) -> (String, String) {
+        let mut long_line = Vec::new();
+        let mut short_line = Vec::new();
+        let mut previous = None;
+        for (name, address) in &self.addresses {
+            // an interface name is only written once before its addresses
+            if previous != Some(name) {
+                long_line.push(name.clone());
+                previous = Some(name);
+            }
+            long_line.push(address.to_string());
+            short_line.push(address.to_string());
+        }
+        match self.public {
+            Some(Some(address)) => {
+                long_line.push(format!("public {address}"));
+                short_line.push(address.to_string());
+            }
+            Some(None) => {
+                long_line.push("public ?".to_string());
+                short_line.push("?".to_string());
+            }
+            None => (),
+        }
+        (long_line.join(" "), short_line.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr, TcpListener},
+        time::Duration,
+    };
+
+    use crate::cmds::{
+        state::{unix_timestamp, StateFile},
+        utils::serve_http,
+    };
+
+    use super::{IpFamily, IpStatus};
+
+    #[test]
+    fn test_ip_addresses() {
+        let addresses = IpStatus::get_addresses().unwrap();
+        assert!(addresses
+            .iter()
+            .any(|(name, address)| name == "lo" && address == &IpAddr::V4(Ipv4Addr::LOCALHOST)));
+
+        let addresses = vec![
+            ("lo".to_string(), "127.0.0.1".parse().unwrap()),
+            ("eth0".to_string(), "192.168.1.10".parse().unwrap()),
+            ("eth0".to_string(), "fe80::1".parse().unwrap()),
+            ("eth0".to_string(), "2001:db8::10".parse().unwrap()),
+            ("wg0".to_string(), "10.0.0.2".parse().unwrap()),
+        ];
+        let filtered = IpStatus::filter_addresses(addresses.clone(), &[], IpFamily::All);
+        assert_eq!(
+            filtered,
+            vec![
+                addresses[1].clone(),
+                addresses[3].clone(),
+                addresses[4].clone()
+            ]
+        );
+
+        let filtered = IpStatus::filter_addresses(
+            addresses,
+            &["wg0".to_string(), "eth0".to_string()],
+            IpFamily::V4,
+        );
+        let ip_status = IpStatus {
+            addresses: filtered,
+            public: Some(Some("203.0.113.7".parse().unwrap())),
+        };
+        assert_eq!(
+            ip_status.i3blocks_print(),
+            (
+                "wg0 10.0.0.2 eth0 192.168.1.10 public 203.0.113.7".to_string(),
+                "10.0.0.2 192.168.1.10 203.0.113.7".to_string()
+            )
+        );
+
+        let ip_status = IpStatus {
+            addresses: ip_status.addresses,
+            public: Some(None),
+        };
+        assert_eq!(
+            ip_status.i3blocks_print(),
+            (
+                "wg0 10.0.0.2 eth0 192.168.1.10 public ?".to_string(),
+                "10.0.0.2 192.168.1.10 ?".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_ip_public() {
+        let (url, server) = serve_http(1, |_| ("200 OK", "203.0.113.7\n".to_string()));
+
+        assert_eq!(
+            IpStatus::fetch_public_ip(&url, Duration::from_secs(1)).unwrap(),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        server.join().unwrap();
+        let (url, server) = serve_http(1, |_| ("200 OK", "<html>blocked</html>".to_string()));
+        assert!(IpStatus::fetch_public_ip(&url, Duration::from_secs(1)).is_err());
+        server.join().unwrap();
+
+        assert_eq!(
+            IpStatus::parse_cache(
+                "1700000000 203.0.113.7 https://api.ipify.org\n",
+                "https://api.ipify.org"
+            ),
+            Some((1700000000, Some("203.0.113.7".parse().unwrap())))
+        );
+        assert_eq!(
+            IpStatus::parse_cache(
+                "1700000000 - https://api.ipify.org\n",
+                "https://api.ipify.org"
+            ),
+            Some((1700000000, None))
+        );
+        assert_eq!(
+            IpStatus::parse_cache(
+                "1700000000 203.0.113.7 https://api.ipify.org\n",
+                "https://ifconfig.me"
+            ),
+            None
+        );
+        assert_eq!(
+            IpStatus::parse_cache("1700000000 203.0.113.7\n", "https://api.ipify.org"),
+            None
+        );
+        assert_eq!(IpStatus::parse_cache("", "https://api.ipify.org"), None);
+    }
+
+    #[test]
+    fn test_ip_public_cache() {
+        let state_dir = std::env::temp_dir().join(format!("ip-{}", std::process::id()));
+        let state = StateFile::in_dir(&state_dir, "public-ip").unwrap();
+        // nothing listens there, every lookup fails
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let down_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let timeout = Duration::from_secs(1);
+        let public = |url: &str| IpStatus::get_public_ip(&state, url, 900, timeout).unwrap();
+        let cached = |url: &str| IpStatus::parse_cache(&state.read(), url);
+        let address = "203.0.113.7".parse::<IpAddr>().unwrap();
+
+        // the failed lookup is cached too
+        let start = unix_timestamp();
+        assert_eq!(public(&down_url), None);
+        assert!(matches!(cached(&down_url), Some((x, None)) if x >= start));
+
+        // a recent lookup isn't done again, even a failed one
+        let (url, server) = serve_http(1, |_| ("200 OK", "203.0.113.7\n".to_string()));
+        state.write(&format!("{start} - {url}\n")).unwrap();
+        assert_eq!(public(&url), None);
+        state.write(&format!("1700000000 - {url}\n")).unwrap();
+        assert_eq!(public(&url), Some(address));
+        server.join().unwrap();
+        assert!(matches!(cached(&url), Some((x, Some(y))) if x >= start && y == address));
+
+        // a stale address is kept when the endpoint can't be reached
+        state
+            .write(&format!("1700000000 203.0.113.7 {down_url}\n"))
+            .unwrap();
+        assert_eq!(public(&down_url), Some(address));
+        assert!(matches!(cached(&down_url), Some((x, Some(_))) if x >= start));
+
+        // but not the address from another endpoint
+        state
+            .write(&format!("1700000000 203.0.113.7 {url}\n"))
+            .unwrap();
+        assert_eq!(public(&down_url), None);
+
+        std::fs::remove_dir_all(state_dir).unwrap();
+    }
+}
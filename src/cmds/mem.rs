@@ -1,9 +1,9 @@
 use clap::{Args, ValueEnum};
 use procfs::Meminfo;
 
-use crate::{BytesUnit, CommandStatus, I3Display, I3DisplayError};
+use crate::{BytesStandard, BytesUnit, CommandStatus, I3Display, I3DisplayError};
 
-use super::utils::define_threshold_color;
+use super::utils::{define_threshold_color, format_bytes};
 
 #[derive(Args)]
 pub struct MemArgs {
@@ -13,6 +13,10 @@ pub struct MemArgs {
     pub warning: u8,
     #[arg(short, long, default_value = "gb")]
     pub unit: BytesUnit,
+    #[arg(long, default_value = "iec")]
+    pub standard: BytesStandard,
+    #[arg(long, default_value_t = 1)]
+    pub precision: usize,
     #[arg(short, long, default_value = "used")]
     pub display: MemoryDisplay,
 }
@@ -34,7 +38,12 @@ pub struct MemStats {
 impl CommandStatus<MemArgs> for MemStats {
     fn get(command: &MemArgs) -> Result<Option<I3Display>, I3DisplayError> {
         let mem_stats = Self::get_mem_stats()?;
-        let lines = mem_stats.i3blocks_print(command.unit, command.display);
+        let lines = mem_stats.i3blocks_print(
+            command.display,
+            command.unit,
+            command.standard,
+            command.precision,
+        );
         let color = define_threshold_color(
             command.warning,
             (command.critical + command.warning) / 2,
@@ -69,37 +78,21 @@ impl MemStats {
         })
     }
 
-    fn i3blocks_print(&self, unit: BytesUnit, display: MemoryDisplay) -> String {
+    fn i3blocks_print(
+        &self,
+        display: MemoryDisplay,
+        unit: BytesUnit,
+        standard: BytesStandard,
+        precision: usize,
+    ) -> String {
         match display {
-            MemoryDisplay::Used => match unit {
-                BytesUnit::Kb => format!("{:.1}K", self.usage_bytes as f64 / 1024.0),
-                BytesUnit::Mb => format!("{:.1}M", self.usage_bytes as f64 / 1024.0 / 1024.0),
-                BytesUnit::Gb => {
-                    format!("{:.1}G", self.usage_bytes as f64 / 1024.0 / 1024.0 / 1024.0)
-                }
-            },
-            MemoryDisplay::Remaining => {
-                let total_memory = match unit {
-                    BytesUnit::Kb => self.total_bytes as f64 / 1024.0,
-                    BytesUnit::Mb => self.total_bytes as f64 / 1024.0 / 1024.0,
-                    BytesUnit::Gb => self.total_bytes as f64 / 1024.0 / 1024.0 / 1024.0,
-                };
-                match unit {
-                    BytesUnit::Kb => {
-                        format!("{:.1}K", total_memory - self.usage_bytes as f64 / 1024.0)
-                    }
-                    BytesUnit::Mb => format!(
-                        "{:.1}M",
-                        total_memory - self.usage_bytes as f64 / 1024.0 / 1024.0
-                    ),
-                    BytesUnit::Gb => {
-                        format!(
-                            "{:.1}G",
-                            total_memory - self.usage_bytes as f64 / 1024.0 / 1024.0 / 1024.0
-                        )
-                    }
-                }
-            }
+            MemoryDisplay::Used => format_bytes(self.usage_bytes as f64, unit, standard, precision),
+            MemoryDisplay::Remaining => format_bytes(
+                (self.total_bytes - self.usage_bytes) as f64,
+                unit,
+                standard,
+                precision,
+            ),
             MemoryDisplay::UsedPercentage => format!("{}%", self.used_percent),
             MemoryDisplay::RemainingPercentage => format!("{}%", 100 - self.used_percent),
         }
@@ -1,10 +1,22 @@
+pub mod cert_check;
 pub mod cpu;
 pub mod disk_io;
 pub mod disk_uage;
+pub mod dns_check;
+pub mod http_check;
+pub mod icmp;
 pub mod icmp_check;
+pub mod ip;
 pub mod mem;
+pub mod moonraker;
 pub mod octoprint;
 pub mod perfmode;
+pub mod power;
+pub mod printer;
 pub mod prusa_link;
+pub mod state;
+pub mod systemd;
 pub mod tcp_check;
 pub mod utils;
+pub mod vpn;
+pub mod wifi;
@@ -0,0 +1,288 @@
+use clap::Args;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+use crate::{CommandStatus, I3Display, I3DisplayError};
+
+use super::printer::{
+    self, Filament, Printer, PrinterDisplayArgs, PrinterError, PrinterField, PrinterInfo,
+    PrinterState, Temperature,
+};
+
+const QUERY_PATH: &str =
+    "/printer/objects/query?print_stats&display_status&virtual_sdcard&extruder&heater_bed";
+
+#[derive(Args)]
+pub struct MoonrakerArgs {
+    #[arg(short, long)]
+    pub url: String,
+    // only needed when moonraker requires authorization for the client
+    #[arg(short, long)]
+    pub apikey: Option<String>,
+    #[command(flatten)]
+    pub display: PrinterDisplayArgs,
+}
+
+// Moonraker API
+#[derive(Debug, Deserialize)]
+pub struct MoonrakerQueryResponse {
+    pub result: MoonrakerQueryResult,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoonrakerQueryResult {
+    pub status: MoonrakerObjects,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoonrakerObjects {
+    pub print_stats: PrintStats,
+    pub display_status: Option<DisplayStatus>,
+    pub virtual_sdcard: Option<VirtualSdcard>,
+    pub extruder: Option<Heater>,
+    pub heater_bed: Option<Heater>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrintStats {
+    pub state: KlipperPrintState,
+    // empty without a job
+    pub filename: String,
+    // time spent printing, pauses excluded
+    pub print_duration: f64,
+    // mm
+    pub filament_used: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DisplayStatus {
+    pub progress: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VirtualSdcard {
+    pub progress: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Heater {
+    pub temperature: f64,
+    pub target: f64,
+}
+
+// https://moonraker.readthedocs.io/en/latest/printer_objects/#print_stats
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum KlipperPrintState {
+    Standby,
+    Printing,
+    Paused,
+    Complete,
+    Cancelled,
+    Error,
+}
+
+impl From<KlipperPrintState> for PrinterState {
+    fn from(state: KlipperPrintState) -> Self {
+        match state {
+            KlipperPrintState::Standby => PrinterState::Idle,
+            KlipperPrintState::Printing => PrinterState::Printing,
+            KlipperPrintState::Paused => PrinterState::Paused,
+            KlipperPrintState::Complete => PrinterState::Finished,
+            KlipperPrintState::Cancelled => PrinterState::Stopped,
+            KlipperPrintState::Error => PrinterState::Attention,
+        }
+    }
+}
+
+pub struct Moonraker {
+    pub url: String,
+    pub apikey: Option<String>,
+}
+
+impl Printer for Moonraker {
+    fn status(
+        &self,
+        client: &Client,
+        _fields: &[PrinterField],
+    ) -> Result<PrinterInfo, PrinterError> {
+        let request_url = format!("{}{QUERY_PATH}", self.url);
+        let res = printer::get(client, &request_url, &self.apikey).send()?;
+        let content: MoonrakerQueryResponse = printer::parse_response(res)?;
+        Ok(Self::to_printer_info(content.result.status))
+    }
+}
+
+impl Moonraker {
+    /// Progress from M73 (`display_status`) when the slicer emits it, file position
+    /// otherwise. Moonraker has no ETA, it's estimated from the printing time so far
+    fn to_printer_info(objects: MoonrakerObjects) -> PrinterInfo {
+        let progress = objects
+            .display_status
+            .map(|x| x.progress)
+            .filter(|x| *x > 0.0)
+            .or(objects.virtual_sdcard.map(|x| x.progress))
+            .unwrap_or(0.0);
+        let print_duration = objects.print_stats.print_duration;
+        let remaining_time = match progress > 0.0 {
+            true => (print_duration / progress - print_duration) as i64,
+            false => 0,
+        };
+
+        let temperature = |x: Option<Heater>| {
+            x.map(|x| Temperature {
+                current: x.temperature,
+                target: x.target,
+            })
+        };
+
+        let print_stats = objects.print_stats;
+        let job = !print_stats.filename.is_empty();
+        let state = PrinterState::from(print_stats.state);
+        PrinterInfo {
+            elapsed_time: job.then_some(print_duration as i64),
+            filament: job.then_some(Filament {
+                length_mm: print_stats.filament_used,
+                volume_cm3: None,
+            }),
+            file: job.then_some(print_stats.filename),
+            nozzle: temperature(objects.extruder),
+            bed: temperature(objects.heater_bed),
+            completion: match state {
+                PrinterState::Idle => 0.0,
+                PrinterState::Finished => 100.0,
+                _ => progress * 100.0,
+            },
+            state,
+            remaining_time,
+        }
+    }
+}
+
+pub struct MoonrakerStatus;
+
+impl CommandStatus<MoonrakerArgs> for MoonrakerStatus {
+    fn get(command: &MoonrakerArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let moonraker = Moonraker {
+            url: command.url.clone(),
+            apikey: command.apikey.clone(),
+        };
+        printer::display(&moonraker, &command.display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cmds::{
+        printer::{self, Printer, PrinterInfo, PrinterState, Temperature},
+        utils::serve_http,
+    };
+
+    use super::{Moonraker, MoonrakerQueryResponse};
+
+    const API_RESULT_PRINTING: &str = r#"
+{
+  "result": {
+    "eventtime": 578243.57824499,
+    "status": {
+      "print_stats": {
+        "filename": "benchy.gcode",
+        "total_duration": 3700.5,
+        "print_duration": 3600.0,
+        "filament_used": 2451.8,
+        "state": "printing",
+        "message": "",
+        "info": { "total_layer": null, "current_layer": null }
+      },
+      "display_status": { "progress": 0.25, "message": null },
+      "virtual_sdcard": {
+        "file_path": "/home/pi/printer_data/gcodes/benchy.gcode",
+        "progress": 0.2312,
+        "is_active": true,
+        "file_position": 231200,
+        "file_size": 1000000
+      },
+      "extruder": { "temperature": 214.6, "target": 215.0, "power": 0.41 },
+      "heater_bed": { "temperature": 60.1, "target": 60.0, "power": 0.22 }
+    }
+  }
+}
+"#;
+
+    const API_RESULT_STANDBY: &str = r#"
+{
+  "result": {
+    "eventtime": 578243.57824499,
+    "status": {
+      "print_stats": {
+        "filename": "",
+        "total_duration": 0.0,
+        "print_duration": 0.0,
+        "filament_used": 0.0,
+        "state": "standby",
+        "message": ""
+      },
+      "display_status": { "progress": 0.0, "message": null },
+      "virtual_sdcard": { "file_path": null, "progress": 0.0, "is_active": false }
+    }
+  }
+}
+"#;
+
+    fn parse(content: &str) -> PrinterInfo {
+        let x = serde_json::from_str::<MoonrakerQueryResponse>(content).unwrap();
+        Moonraker::to_printer_info(x.result.status)
+    }
+
+    #[test]
+    fn test_moonraker_status() {
+        let printing = parse(API_RESULT_PRINTING);
+        assert_eq!(printing.completion, 25.0);
+        // 3600s for 25%, 10800s left
+        assert_eq!(printing.remaining_time, 10800);
+        assert_eq!(printing.file, Some("benchy.gcode".to_string()));
+        assert_eq!(printing.elapsed_time, Some(3600));
+        assert_eq!(printing.filament.map(|x| x.length_mm), Some(2451.8));
+        assert_eq!(
+            printing.nozzle,
+            Some(Temperature {
+                current: 214.6,
+                target: 215.0
+            })
+        );
+
+        // without M73, the file position is used
+        let printing = parse(&API_RESULT_PRINTING.replace("\"progress\": 0.25", "\"progress\": 0"));
+        assert!((printing.completion - 23.12).abs() < 1e-9);
+
+        let standby = parse(API_RESULT_STANDBY);
+        assert_eq!(standby.state, PrinterState::Idle);
+        assert_eq!(standby.completion, 0.0);
+        assert_eq!(standby.remaining_time, 0);
+        assert_eq!(standby.bed, None);
+        assert_eq!(standby.file, None);
+        assert_eq!(standby.elapsed_time, None);
+
+        let complete = parse(&API_RESULT_PRINTING.replace("\"printing\"", "\"complete\""));
+        assert_eq!(complete.state, PrinterState::Finished);
+        assert_eq!(complete.completion, 100.0);
+        let error = parse(&API_RESULT_PRINTING.replace("\"printing\"", "\"error\""));
+        assert_eq!(error.state, PrinterState::Attention);
+    }
+
+    #[test]
+    fn test_moonraker_api_key() {
+        let (url, server) = serve_http(1, |_| ("200 OK", API_RESULT_PRINTING.to_string()));
+
+        let moonraker = Moonraker {
+            url,
+            apikey: Some("secret".to_string()),
+        };
+        let info = moonraker.status(&printer::client().unwrap(), &[]).unwrap();
+        assert_eq!(info.state, PrinterState::Printing);
+        let request = server.join().unwrap()[0].to_lowercase();
+        assert!(request
+            .starts_with("get /printer/objects/query?print_stats&display_status&virtual_sdcard&extruder&heater_bed "));
+        assert!(request.contains("x-api-key: secret"));
+    }
+}
@@ -1,12 +1,13 @@
-use std::fmt::Display;
-
 use clap::Args;
-use compound_duration::format_dhms;
-use reqwest::{blocking::Response, StatusCode};
+use reqwest::blocking::Client;
+use serde::Deserialize;
 
 use crate::{CommandStatus, I3Display, I3DisplayError};
-use reqwest::header::{self, HeaderValue};
-use serde::Deserialize;
+
+use super::printer::{
+    self, Filament, Printer, PrinterDisplayArgs, PrinterError, PrinterField, PrinterInfo,
+    PrinterState, Temperature,
+};
 
 #[derive(Args)]
 pub struct OctoprintArgs {
@@ -14,54 +15,67 @@ pub struct OctoprintArgs {
     pub apikey: String,
     #[arg(short, long)]
     pub url: String,
-    #[arg(short = 'r', long, default_value_t = false)]
-    pub hide_remaining_time: bool,
+    #[command(flatten)]
+    pub display: PrinterDisplayArgs,
 }
 
 // Octoprint API
 #[derive(Debug, Deserialize)]
 pub struct OctoprintApiJobResponse {
+    pub job: ApiJob,
     pub progress: ApiProgress,
     pub state: OctoprintJobState,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ApiJob {
+    pub file: ApiFile,
+    // needed for the whole job, per tool
+    pub filament: Option<ApiFilaments>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiFile {
+    pub display: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiFilaments {
+    pub tool0: Option<ApiFilament>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiFilament {
+    // mm
+    pub length: Option<f64>,
+    // cm³, 0 when unknown
+    pub volume: Option<f64>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ApiProgress {
     pub completion: Option<f64>,
+    #[serde(rename(deserialize = "printTime"))]
+    pub print_time: Option<i64>,
     #[serde(rename(deserialize = "printTimeLeft"))]
     pub print_time_left: Option<i64>,
 }
 
-#[derive(Debug)]
-pub struct OctoprintStatus {
-    status: OctoprintJobState,
-    remaining_time: i64,
-    completion: f64,
+#[derive(Debug, Deserialize)]
+pub struct OctoprintApiPrinterResponse {
+    pub temperature: ApiTemperatures,
 }
 
-#[derive(Debug)]
-pub enum OctoprintStatusError {
-    ConnectionRefused,
-    ConnectionTimeout,
-    InvalidApiKey(String),
-    InvalidResponse(String),
-    InvalidConnection(String),
-    DeserializationError(String),
+#[derive(Debug, Deserialize)]
+pub struct ApiTemperatures {
+    pub tool0: Option<ApiTemperature>,
+    pub bed: Option<ApiTemperature>,
 }
 
-impl Display for OctoprintStatusError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OctoprintStatusError::ConnectionRefused => write!(f, "Connection refused"),
-            OctoprintStatusError::InvalidApiKey(x) => write!(f, "Invalid api key: {}", x),
-            OctoprintStatusError::InvalidResponse(x) => write!(f, "Invalid response: {}", x),
-            OctoprintStatusError::InvalidConnection(x) => write!(f, "Invalid connection: {}", x),
-            OctoprintStatusError::DeserializationError(x) => {
-                write!(f, "Deserialization error: {}", x)
-            }
-            OctoprintStatusError::ConnectionTimeout => write!(f, "Connection timeout"),
-        }
-    }
+#[derive(Debug, Deserialize)]
+pub struct ApiTemperature {
+    pub actual: Option<f64>,
+    pub target: Option<f64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -77,128 +91,116 @@ pub enum OctoprintJobState {
     OfflineAfterError,
 }
 
-impl Display for OctoprintJobState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            OctoprintJobState::Operational => write!(f, "󰒲"),
-            OctoprintJobState::OfflineAfterError => write!(f, "󰒲"),
-            OctoprintJobState::Printing => write!(f, "Printing"),
-            OctoprintJobState::Pausing => write!(f, ""),
-            OctoprintJobState::Paused => write!(f, ""),
-            OctoprintJobState::Cancelling => write!(f, ""),
-            OctoprintJobState::Error => write!(f, ""),
-            OctoprintJobState::Offline => write!(f, "Offline"),
+impl From<OctoprintJobState> for PrinterState {
+    fn from(state: OctoprintJobState) -> Self {
+        match state {
+            OctoprintJobState::Operational => PrinterState::Idle,
+            OctoprintJobState::Printing => PrinterState::Printing,
+            OctoprintJobState::Pausing | OctoprintJobState::Paused => PrinterState::Paused,
+            OctoprintJobState::Cancelling => PrinterState::Busy,
+            OctoprintJobState::Error => PrinterState::Attention,
+            OctoprintJobState::Offline | OctoprintJobState::OfflineAfterError => {
+                PrinterState::Offline
+            }
         }
     }
 }
 
-impl CommandStatus<OctoprintArgs> for OctoprintStatus {
-    fn get(command: &OctoprintArgs) -> Result<Option<I3Display>, I3DisplayError> {
-        let res = match Self::get_job_status(command.url.clone(), command.apikey.clone()) {
-            Ok(x) => x,
-            Err(e) => match e {
-                OctoprintStatusError::ConnectionRefused
-                | OctoprintStatusError::ConnectionTimeout => return Ok(None),
-                _ => return Err(I3DisplayError::from(e.to_string())),
-            },
-        };
-
-        let octoprint_status = Self::to_octoprint_status(res)
-            .map_err(|e| I3DisplayError::from(format!("Error: {}", e)))?;
+pub struct Octoprint {
+    pub url: String,
+    pub apikey: Option<String>,
+}
 
-        let line = match octoprint_status.status {
-            OctoprintJobState::Printing => {
-                let mut x = format!("{:.1}%", octoprint_status.completion);
-                if !command.hide_remaining_time {
-                    x = format!(
-                        "{} {}",
-                        x,
-                        format_dhms(octoprint_status.remaining_time as usize)
-                    );
-                }
-                x
-            }
-            _ => octoprint_status.status.to_string(),
+impl Printer for Octoprint {
+    fn status(
+        &self,
+        client: &Client,
+        fields: &[PrinterField],
+    ) -> Result<PrinterInfo, PrinterError> {
+        let res = printer::get(client, &format!("{}/api/job", self.url), &self.apikey).send()?;
+        let content: OctoprintApiJobResponse = printer::parse_response(res)?;
+        let state = PrinterState::from(content.state);
+        let temperatures = match state {
+            PrinterState::Offline => None,
+            _ if fields.contains(&PrinterField::Temperatures) => self.temperatures(client),
+            _ => None,
         };
-        Ok(Some(I3Display::new(None, line.clone(), line, None)))
+        let temperature =
+            |x: Option<ApiTemperature>| x.and_then(|x| Temperature::new(x.actual, x.target));
+        let (nozzle, bed) = match temperatures {
+            Some(x) => (temperature(x.tool0), temperature(x.bed)),
+            None => (None, None),
+        };
+        let completion = content.progress.completion.unwrap_or(0.0);
+        // only the total is known, the used part follows the progress
+        let filament = content.job.filament.and_then(|x| x.tool0).and_then(|x| {
+            let ratio = completion / 100.0;
+            Some(Filament {
+                length_mm: x.length? * ratio,
+                volume_cm3: x.volume.filter(|x| *x > 0.0).map(|x| x * ratio),
+            })
+        });
+        Ok(PrinterInfo {
+            state,
+            completion,
+            remaining_time: content.progress.print_time_left.unwrap_or(0),
+            nozzle,
+            bed,
+            file: content.job.file.display,
+            elapsed_time: content.progress.print_time,
+            filament,
+        })
     }
 }
 
-impl OctoprintStatus {
-    fn get_job_status(url: String, apikey: String) -> Result<Response, OctoprintStatusError> {
-        // url
-        let request_url = format!("{url}/api/job");
-        // headers
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            "X-Api-Key",
-            HeaderValue::from_str(apikey.as_str())
-                .map_err(|e| OctoprintStatusError::InvalidApiKey(e.to_string()))?,
-        );
-
-        let client = reqwest::blocking::Client::builder()
-            .default_headers(headers)
-            .timeout(std::time::Duration::from_secs(1))
-            .build()
-            .map_err(|e| OctoprintStatusError::InvalidConnection(e.to_string()))?;
-
-        client.get(request_url).send().map_err(|e| {
-            if e.is_timeout() {
-                OctoprintStatusError::ConnectionTimeout
-            } else if e.is_connect() {
-                OctoprintStatusError::ConnectionRefused
-            } else {
-                OctoprintStatusError::InvalidResponse(e.to_string())
-            }
-        })
+impl Octoprint {
+    /// Temperatures come from another endpoint, answering 409 when the printer isn't
+    /// connected: they are just left out when unavailable
+    fn temperatures(&self, client: &Client) -> Option<ApiTemperatures> {
+        let res = printer::get(
+            client,
+            &format!("{}/api/printer?exclude=sd,state", self.url),
+            &self.apikey,
+        )
+        .send()
+        .ok()?;
+        printer::parse_response::<OctoprintApiPrinterResponse>(res)
+            .ok()
+            .map(|x| x.temperature)
     }
+}
 
-    fn to_octoprint_status(res: Response) -> Result<OctoprintStatus, OctoprintStatusError> {
-        let content = match res.status() {
-            StatusCode::OK => {
-                let x: OctoprintApiJobResponse = res
-                    .json()
-                    .map_err(|e| OctoprintStatusError::DeserializationError(e.to_string()))?;
-                x
-            }
-            StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => {
-                return Err(OctoprintStatusError::ConnectionTimeout)
-            }
-            StatusCode::FORBIDDEN => {
-                return Err(OctoprintStatusError::InvalidApiKey(
-                    "Connection forbidden: invalid api key?".to_string(),
-                ))
-            }
-            _ => {
-                return Err(OctoprintStatusError::InvalidResponse(format!(
-                    "Error: {}",
-                    res.status()
-                )))
-            }
+pub struct OctoprintStatus;
+
+impl CommandStatus<OctoprintArgs> for OctoprintStatus {
+    fn get(command: &OctoprintArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let octoprint = Octoprint {
+            url: command.url.clone(),
+            apikey: Some(command.apikey.clone()),
         };
-        Ok(OctoprintStatus {
-            status: content.state,
-            completion: content.progress.completion.unwrap_or(0.0),
-            remaining_time: content.progress.print_time_left.unwrap_or(0),
-        })
+        printer::display(&octoprint, &command.display)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{OctoprintApiJobResponse, OctoprintStatus};
+    use crate::cmds::{
+        printer::{self, Printer, PrinterField},
+        utils::serve_http,
+    };
+
+    use super::{Octoprint, OctoprintApiJobResponse, OctoprintApiPrinterResponse};
     const OCTOPRINT_URL: &str = "";
     const OCTOPRINT_API_KEY: &str = "";
 
     #[test]
     #[ignore = "can only be tested locally"]
     fn test_octoprint_api_connectivity() {
-        let x = OctoprintStatus::get_job_status(
-            OCTOPRINT_URL.to_string(),
-            OCTOPRINT_API_KEY.to_string(),
-        );
-        assert!(x.is_ok());
-        let res = OctoprintStatus::to_octoprint_status(x.unwrap());
+        let octoprint = Octoprint {
+            url: OCTOPRINT_URL.to_string(),
+            apikey: Some(OCTOPRINT_API_KEY.to_string()),
+        };
+        let res = octoprint.status(&printer::client().unwrap(), &[PrinterField::Temperatures]);
         assert!(res.is_ok());
         println!("{:?}", res.unwrap());
     }
@@ -270,7 +272,60 @@ mod tests {
         let boot = serde_json::from_str::<OctoprintApiJobResponse>(api_result_boot);
         assert!(boot.is_ok());
 
-        let x = serde_json::from_str::<OctoprintApiJobResponse>(api_result);
-        assert!(x.is_ok());
+        let x = serde_json::from_str::<OctoprintApiJobResponse>(api_result).unwrap();
+        assert_eq!(x.job.file.display, Some("x.gcode".to_string()));
+        assert_eq!(x.progress.print_time, Some(0));
+        assert_eq!(
+            x.job.filament.and_then(|x| x.tool0).and_then(|x| x.length),
+            Some(1416.7206100000044)
+        );
+
+        // api/printer?exclude=sd,state
+        let api_result_printer = r#"
+{
+  "temperature": {
+    "bed": { "actual": 59.8, "offset": 0, "target": 60.0 },
+    "tool0": { "actual": 214.8, "offset": 0, "target": 215.0 }
+  }
+}
+        "#;
+        let x = serde_json::from_str::<OctoprintApiPrinterResponse>(api_result_printer).unwrap();
+        let tool0 = x.temperature.tool0.unwrap();
+        assert_eq!((tool0.actual, tool0.target), (Some(214.8), Some(215.0)));
+    }
+
+    #[test]
+    fn test_octoprint_temperatures_request() {
+        let api_job = r#"{"job": {"file": {"display": "x.gcode"}, "filament": null},
+            "progress": {"completion": 50.0, "printTime": 60, "printTimeLeft": 60},
+            "state": "Printing"}"#;
+        let api_printer = r#"{"temperature": {"tool0": {"actual": 214.8, "target": 215.0}}}"#;
+        // one request with the file only, two with the temperatures
+        let (url, server) = serve_http(3, move |request| {
+            match request.starts_with("GET /api/job ") {
+                true => ("200 OK", api_job.to_string()),
+                false => ("200 OK", api_printer.to_string()),
+            }
+        });
+
+        let octoprint = Octoprint { url, apikey: None };
+        let client = printer::client().unwrap();
+        let info = octoprint.status(&client, &[PrinterField::File]).unwrap();
+        assert_eq!(info.nozzle, None);
+        let info = octoprint
+            .status(&client, &[PrinterField::Temperatures])
+            .unwrap();
+        assert_eq!(info.nozzle.map(|x| x.target), Some(215.0));
+
+        let paths = server
+            .join()
+            .unwrap()
+            .iter()
+            .map(|x| x.split(' ').nth(1).unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["/api/job", "/api/job", "/api/printer?exclude=sd,state"]
+        );
     }
 }
@@ -1,14 +1,34 @@
 use clap::{Args, ValueEnum};
-use std::{fmt, fs};
+use std::{collections::HashMap, fmt, fs, path::Path};
+use zbus::{blocking::Connection, zvariant::OwnedValue};
 
 use crate::{CommandStatus, I3Display, I3DisplayError};
 
+use super::utils::parse_key_value;
+
 const PERF_PROFILE: &str = "/sys/firmware/acpi/platform_profile";
+const PERF_PROFILE_CHOICES: &str = "/sys/firmware/acpi/platform_profile_choices";
+const POWER_PROFILES_DESTINATION: &str = "net.hadess.PowerProfiles";
+const POWER_PROFILES_PATH: &str = "/net/hadess/PowerProfiles";
+const POWER_PROFILES_INTERFACE: &str = "net.hadess.PowerProfiles";
+const TUNED_DESTINATION: &str = "com.redhat.tuned";
+const TUNED_PATH: &str = "/Tuned";
+const TUNED_INTERFACE: &str = "com.redhat.tuned.control";
 
 #[derive(Args)]
 pub struct PerfModeArgs {
     #[arg(short, long, default_value = "icons")]
     pub display: PerfModeStyle,
+    #[arg(short, long, default_value = "auto")]
+    pub backend: PerfModeBackend,
+    #[arg(short, long, conflicts_with = "cycle")]
+    pub set: Option<String>,
+    #[arg(short, long, default_value_t = false)]
+    pub cycle: bool,
+    #[arg(short, long, value_parser = parse_key_value)]
+    pub icon: Vec<(String, String)>,
+    #[arg(short, long, value_parser = parse_key_value)]
+    pub text: Vec<(String, String)>,
 }
 
 #[derive(Clone, Copy, ValueEnum)]
@@ -17,64 +37,429 @@ pub enum PerfModeStyle {
     Text,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PerfModeBackend {
+    // first running daemon, falling back to sysfs
+    Auto,
+    // ACPI platform profile, switching requires root
+    Sysfs,
+    // power-profiles-daemon over D-Bus, switching allowed to regular users
+    PowerProfilesDaemon,
+    // tuned over D-Bus
+    Tuned,
+}
+
+enum ProfileSource {
+    Sysfs,
+    PowerProfilesDaemon(Connection),
+    Tuned(Connection),
+}
+
+// default icons for the usual profiles, others are displayed as text
+const PROFILE_ICONS: [(&str, &str); 4] = [
+    ("balanced", "󰾅"),
+    ("performance", "󰓅"),
+    ("low-power", "󰾆"),
+    ("power-saver", "󰾆"),
+];
+
 #[derive(Debug)]
-pub enum PerformanceMode {
-    // Fr+,/m
-    Balanced,
-    // Fn+h
-    Performance,
-    // Fn+l
-    LowPower,
+pub struct PerformanceMode {
+    profile: String,
 }
 
 impl fmt::Display for PerformanceMode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            PerformanceMode::Balanced => write!(f, "Balanced"),
-            PerformanceMode::Performance => write!(f, "Performance"),
-            PerformanceMode::LowPower => write!(f, "Low Power"),
-        }
+        // low-power => Low Power
+        let text = self
+            .profile
+            .split('-')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        write!(f, "{text}")
     }
 }
 
 impl CommandStatus<PerfModeArgs> for PerformanceMode {
     fn get(command: &PerfModeArgs) -> Result<Option<I3Display>, I3DisplayError> {
-        let lines = Self::i3blocks_print(command.display)?;
+        let source = ProfileSource::new(command.backend)?;
+        if let Some(profile) = &command.set {
+            source.set_profile(profile)?;
+        } else if command.cycle {
+            let choices = source.get_choices()?;
+            let current = source.get_profile()?;
+            source.set_profile(&Self::next_profile(&choices, &current))?;
+        }
+
+        let mode = Self::get_mode(&source)?;
+        let lines = mode.i3blocks_print(command.display, &command.icon, &command.text);
         Ok(Some(I3Display::new(None, lines.clone(), lines, None)))
     }
 }
 
 impl PerformanceMode {
-    pub fn i3blocks_print(style: PerfModeStyle) -> Result<String, I3DisplayError> {
-        let mode = Self::get_mode()?;
-        Ok(match style {
-            PerfModeStyle::Icons => match mode {
-                PerformanceMode::Balanced => "󰾅",
-                PerformanceMode::Performance => "󰓅",
-                PerformanceMode::LowPower => "󰾆",
-            }
-            .to_string(),
-            PerfModeStyle::Text => mode.to_string(),
-        })
+    pub fn i3blocks_print(
+        &self,
+        style: PerfModeStyle,
+        icons: &[(String, String)],
+        texts: &[(String, String)],
+    ) -> String {
+        let user_mapping = |mapping: &[(String, String)]| {
+            mapping
+                .iter()
+                .find(|(profile, _)| *profile == self.profile)
+                .map(|(_, x)| x.clone())
+        };
+        match style {
+            PerfModeStyle::Icons => user_mapping(icons)
+                .or(PROFILE_ICONS
+                    .iter()
+                    .find(|(profile, _)| *profile == self.profile)
+                    .map(|(_, x)| x.to_string()))
+                .unwrap_or(self.to_string()),
+            PerfModeStyle::Text => user_mapping(texts).unwrap_or(self.to_string()),
+        }
     }
 
-    fn get_mode() -> Result<PerformanceMode, I3DisplayError> {
-        let content = fs::read_to_string(PERF_PROFILE)
-            .map_err(|e| I3DisplayError::from(format!("can't read file {PERF_PROFILE}: {e}")))?;
+    fn get_mode(source: &ProfileSource) -> Result<PerformanceMode, I3DisplayError> {
+        let profile = source.get_profile()?;
+        let choices = source.get_choices()?;
 
-        match content.as_str().trim() {
-            "balanced" => Ok(PerformanceMode::Balanced),
-            "performance" => Ok(PerformanceMode::Performance),
-            "low-power" => Ok(PerformanceMode::LowPower),
-            _ => Err(I3DisplayError::from(format!(
-                "unknown performance mode: `{content}`"
+        match choices.contains(&profile) {
+            true => Ok(PerformanceMode { profile }),
+            false => Err(I3DisplayError::from(format!(
+                "unknown performance mode: `{profile}`"
             ))),
         }
     }
+
+    /// Profile following the current one in the available choices
+    fn next_profile(choices: &[String], current: &str) -> String {
+        let position = choices.iter().position(|x| x == current);
+        match position {
+            Some(x) => choices[(x + 1) % choices.len()].clone(),
+            None => choices
+                .first()
+                .map(|x| x.as_str())
+                .unwrap_or(current)
+                .to_string(),
+        }
+    }
+}
+
+impl ProfileSource {
+    fn new(backend: PerfModeBackend) -> Result<Self, I3DisplayError> {
+        let system_bus = || {
+            Connection::system()
+                .map_err(|e| I3DisplayError::from(format!("can't connect to system bus: {e}")))
+        };
+        match backend {
+            PerfModeBackend::Auto => Ok(match system_bus() {
+                Ok(x) => Self::detect(x),
+                Err(_) => ProfileSource::Sysfs,
+            }),
+            PerfModeBackend::Sysfs => Ok(ProfileSource::Sysfs),
+            PerfModeBackend::PowerProfilesDaemon => {
+                Ok(ProfileSource::PowerProfilesDaemon(system_bus()?))
+            }
+            PerfModeBackend::Tuned => Ok(ProfileSource::Tuned(system_bus()?)),
+        }
+    }
+
+    /// Use power-profiles-daemon or tuned when running, sysfs otherwise
+    fn detect(connection: Connection) -> Self {
+        let is_running = |name: &str| {
+            zbus::blocking::fdo::DBusProxy::new(&connection)
+                .and_then(|x| Ok(x.name_has_owner(name.try_into()?)?))
+                .unwrap_or(false)
+        };
+        if is_running(POWER_PROFILES_DESTINATION) {
+            ProfileSource::PowerProfilesDaemon(connection)
+        } else if is_running(TUNED_DESTINATION) {
+            ProfileSource::Tuned(connection)
+        } else {
+            ProfileSource::Sysfs
+        }
+    }
+
+    fn get_profile(&self) -> Result<String, I3DisplayError> {
+        match self {
+            ProfileSource::Sysfs => Self::read_sysfs(Path::new(PERF_PROFILE)),
+            ProfileSource::PowerProfilesDaemon(x) => Self::power_profiles_proxy(x)?
+                .get_property::<String>("ActiveProfile")
+                .map_err(|e| I3DisplayError::from(format!("can't get active profile: {e}"))),
+            ProfileSource::Tuned(x) => Self::tuned_proxy(x)?
+                .call("active_profile", &())
+                .map_err(|e| I3DisplayError::from(format!("can't get active profile: {e}"))),
+        }
+    }
+
+    fn get_choices(&self) -> Result<Vec<String>, I3DisplayError> {
+        match self {
+            ProfileSource::Sysfs => Ok(Self::read_sysfs(Path::new(PERF_PROFILE_CHOICES))?
+                .split_whitespace()
+                .map(|x| x.to_string())
+                .collect()),
+            ProfileSource::PowerProfilesDaemon(x) => {
+                let profiles = Self::power_profiles_proxy(x)?
+                    .get_property::<Vec<HashMap<String, OwnedValue>>>("Profiles")
+                    .map_err(|e| I3DisplayError::from(format!("can't get profiles: {e}")))?;
+                Ok(profiles
+                    .iter()
+                    .filter_map(|x| String::try_from(&**x.get("Profile")?).ok())
+                    .collect())
+            }
+            ProfileSource::Tuned(x) => Self::tuned_proxy(x)?
+                .call("profiles", &())
+                .map_err(|e| I3DisplayError::from(format!("can't get profiles: {e}"))),
+        }
+    }
+
+    fn set_profile(&self, profile: &str) -> Result<(), I3DisplayError> {
+        let choices = self.get_choices()?;
+        if !choices.iter().any(|x| x == profile) {
+            return Err(I3DisplayError::from(format!(
+                "unknown performance mode `{profile}`, expected one of: {}",
+                choices.join(", ")
+            )));
+        }
+
+        match self {
+            ProfileSource::Sysfs => fs::write(PERF_PROFILE, profile)
+                .map_err(|e| I3DisplayError::from(format!("can't write file {PERF_PROFILE}: {e}"))),
+            ProfileSource::PowerProfilesDaemon(x) => Self::power_profiles_proxy(x)?
+                .set_property("ActiveProfile", profile)
+                .map_err(|e| I3DisplayError::from(format!("can't set active profile: {e}"))),
+            ProfileSource::Tuned(x) => {
+                let (success, message): (bool, String) = Self::tuned_proxy(x)?
+                    .call("switch_profile", &(profile,))
+                    .map_err(|e| I3DisplayError::from(format!("can't set active profile: {e}")))?;
+                match success {
+                    true => Ok(()),
+                    false => Err(I3DisplayError::from(format!(
+                        "can't set active profile: {message}"
+                    ))),
+                }
+            }
+        }
+    }
+
+    fn read_sysfs(path: &Path) -> Result<String, I3DisplayError> {
+        fs::read_to_string(path)
+            .map(|x| x.trim().to_string())
+            .map_err(|e| I3DisplayError::from(format!("can't read file {}: {e}", path.display())))
+    }
+
+    fn power_profiles_proxy(
+        connection: &Connection,
+    ) -> Result<zbus::blocking::Proxy<'_>, I3DisplayError> {
+        zbus::blocking::Proxy::new(
+            connection,
+            POWER_PROFILES_DESTINATION,
+            POWER_PROFILES_PATH,
+            POWER_PROFILES_INTERFACE,
+        )
+        .map_err(|e| I3DisplayError::from(format!("can't reach power-profiles-daemon: {e}")))
+    }
+
+    fn tuned_proxy(connection: &Connection) -> Result<zbus::blocking::Proxy<'_>, I3DisplayError> {
+        zbus::blocking::Proxy::new(connection, TUNED_DESTINATION, TUNED_PATH, TUNED_INTERFACE)
+            .map_err(|e| I3DisplayError::from(format!("can't reach tuned: {e}")))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use zbus::{
+        blocking::connection,
+        zvariant::{OwnedValue, Value},
+    };
+
+    use super::{
+        PerfModeStyle, PerformanceMode, ProfileSource, POWER_PROFILES_DESTINATION,
+        POWER_PROFILES_PATH, TUNED_DESTINATION, TUNED_PATH,
+    };
+    use crate::cmds::utils::spawn_session_bus;
+
+    struct FakeTuned {
+        active_profile: String,
+    }
+
+    #[zbus::interface(name = "com.redhat.tuned.control")]
+    impl FakeTuned {
+        #[zbus(name = "active_profile")]
+        fn active_profile(&self) -> String {
+            self.active_profile.clone()
+        }
+
+        #[zbus(name = "profiles")]
+        fn profiles(&self) -> Vec<String> {
+            ["balanced", "powersave", "latency-performance"]
+                .map(|x| x.to_string())
+                .to_vec()
+        }
+
+        #[zbus(name = "switch_profile")]
+        fn switch_profile(&mut self, profile: String) -> (bool, String) {
+            match profile.as_str() {
+                "latency-performance" => (false, "profile is broken".to_string()),
+                _ => {
+                    self.active_profile = profile;
+                    (true, "OK".to_string())
+                }
+            }
+        }
+    }
+
+    struct FakePowerProfiles {
+        active_profile: String,
+    }
+
+    #[zbus::interface(name = "net.hadess.PowerProfiles")]
+    impl FakePowerProfiles {
+        #[zbus(property)]
+        fn active_profile(&self) -> String {
+            self.active_profile.clone()
+        }
+
+        #[zbus(property)]
+        fn set_active_profile(&mut self, profile: String) {
+            self.active_profile = profile;
+        }
+
+        #[zbus(property)]
+        fn profiles(&self) -> Vec<HashMap<String, OwnedValue>> {
+            ["power-saver", "balanced", "performance"]
+                .iter()
+                .map(|x| {
+                    HashMap::from([("Profile".to_string(), Value::from(*x).try_into().unwrap())])
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_perfmode_print() {
+        let mode = |profile: &str| PerformanceMode {
+            profile: profile.to_string(),
+        };
+        let icons = vec![("quiet".to_string(), "Q".to_string())];
+        let texts = vec![("balanced".to_string(), "Bal".to_string())];
+
+        let icon = |profile| mode(profile).i3blocks_print(PerfModeStyle::Icons, &icons, &texts);
+        assert_eq!(icon("performance"), "󰓅");
+        assert_eq!(icon("quiet"), "Q");
+        assert_eq!(icon("cool"), "Cool");
+
+        let text = |profile| mode(profile).i3blocks_print(PerfModeStyle::Text, &icons, &texts);
+        assert_eq!(text("low-power"), "Low Power");
+        assert_eq!(text("balanced-performance"), "Balanced Performance");
+        assert_eq!(text("balanced"), "Bal");
+    }
+
+    #[test]
+    fn test_perfmode_next_profile() {
+        let choices = ["low-power", "balanced", "performance"].map(|x| x.to_string());
+        assert_eq!(
+            PerformanceMode::next_profile(&choices, "low-power"),
+            "balanced"
+        );
+        assert_eq!(
+            PerformanceMode::next_profile(&choices, "performance"),
+            "low-power"
+        );
+        assert_eq!(
+            PerformanceMode::next_profile(&choices, "custom"),
+            "low-power"
+        );
+        assert_eq!(PerformanceMode::next_profile(&[], "custom"), "custom");
+    }
+
     #[test]
-    fn test_perfmode_print() {}
+    #[ignore = "needs dbus-daemon"]
+    fn test_perfmode_power_profiles_daemon() {
+        let (mut dbus_daemon, address) = spawn_session_bus().expect("dbus-daemon not found");
+
+        let power_profiles = FakePowerProfiles {
+            active_profile: "balanced".to_string(),
+        };
+        let _service = connection::Builder::address(address.as_str())
+            .unwrap()
+            .name(POWER_PROFILES_DESTINATION)
+            .unwrap()
+            .serve_at(POWER_PROFILES_PATH, power_profiles)
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let source = ProfileSource::detect(client);
+        let detected = matches!(source, ProfileSource::PowerProfilesDaemon(_));
+        let choices = source.get_choices();
+        let set = source.set_profile("performance");
+        let invalid = source.set_profile("turbo");
+        let active = source.get_profile();
+        dbus_daemon.kill().unwrap();
+
+        assert!(detected);
+        assert_eq!(
+            choices.unwrap(),
+            vec!["power-saver", "balanced", "performance"]
+        );
+        assert!(set.is_ok());
+        assert!(invalid.is_err());
+        assert_eq!(active.unwrap(), "performance");
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn test_perfmode_tuned() {
+        let (mut dbus_daemon, address) = spawn_session_bus().expect("dbus-daemon not found");
+        let client = connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let nothing_running = matches!(ProfileSource::detect(client), ProfileSource::Sysfs);
+
+        let tuned = FakeTuned {
+            active_profile: "balanced".to_string(),
+        };
+        let _service = connection::Builder::address(address.as_str())
+            .unwrap()
+            .name(TUNED_DESTINATION)
+            .unwrap()
+            .serve_at(TUNED_PATH, tuned)
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let source = ProfileSource::detect(client);
+        let detected = matches!(source, ProfileSource::Tuned(_));
+        let set = source.set_profile("powersave");
+        let active = source.get_profile();
+        let refused = source.set_profile("latency-performance");
+        dbus_daemon.kill().unwrap();
+
+        assert!(nothing_running);
+        assert!(detected);
+        assert!(set.is_ok());
+        assert_eq!(active.unwrap(), "powersave");
+        assert!(refused.is_err());
+    }
 }
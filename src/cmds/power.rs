@@ -0,0 +1,405 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::Duration,
+};
+
+use clap::{Args, ValueEnum};
+
+use crate::{CommandStatus, I3Display, I3DisplayError};
+
+use super::{
+    state::{unix_timestamp_ms, StateFile},
+    utils::define_threshold_color,
+};
+
+const POWERCAP_PATH: &str = "/sys/class/powercap";
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+const STATE_FILE_NAME: &str = "power";
+// delay between 2 energy samples when there is no usable previous run
+const SAMPLE_INTERVAL_MS: u64 = 500;
+// older runs are too far away to give an instantaneous power
+const MAX_STATE_AGE_MS: u64 = 60 * 1000;
+
+#[derive(Args)]
+pub struct PowerArgs {
+    #[arg(short, long, default_value_t=45, value_parser = clap::value_parser!(u8).range(2..))]
+    pub critical: u8,
+    #[arg(short, long, default_value_t = 25, value_parser = clap::value_parser!(u8).range(1..))]
+    pub warning: u8,
+    #[arg(short, long, default_value = "all")]
+    pub display: PowerDisplay,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PowerDisplay {
+    All,
+    Package,
+    Battery,
+}
+
+// energy counter of a RAPL package zone, in microjoules
+#[derive(Debug, PartialEq)]
+struct EnergySample {
+    zone: String,
+    energy_uj: u64,
+    max_energy_uj: u64,
+}
+
+#[derive(Debug)]
+pub struct PowerStats {
+    package_watts: Option<f64>,
+    battery_watts: Option<f64>,
+}
+
+impl CommandStatus<PowerArgs> for PowerStats {
+    fn get(command: &PowerArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let package_watts = match command.display {
+            PowerDisplay::All | PowerDisplay::Package => {
+                Self::get_package_power(Path::new(POWERCAP_PATH))?
+            }
+            PowerDisplay::Battery => None,
+        };
+        let battery_watts = match command.display {
+            PowerDisplay::All | PowerDisplay::Battery => {
+                Self::get_battery_power(Path::new(POWER_SUPPLY_PATH))
+            }
+            PowerDisplay::Package => None,
+        };
+        let power_stats = PowerStats {
+            package_watts,
+            battery_watts,
+        };
+
+        let lines = power_stats.i3blocks_print();
+        if lines.is_empty() {
+            return Err(I3DisplayError::from(
+                "no readable RAPL energy counter nor discharging battery found".to_string(),
+            ));
+        }
+        let color = define_threshold_color(
+            command.warning,
+            Self::danger_threshold(command.warning, command.critical),
+            command.critical,
+            power_stats
+                .package_watts
+                .or(power_stats.battery_watts)
+                .unwrap_or(0.0) as f32,
+        );
+        Ok(Some(I3Display::new(
+            None,
+            lines.clone(),
+            lines,
+            Some(color),
+        )))
+    }
+}
+
+impl PowerStats {
+    /// Midpoint between warning and critical, computed wide to not overflow `u8`
+    fn danger_threshold(warning: u8, critical: u8) -> u8 {
+        ((warning as u16 + critical as u16) / 2) as u8
+    }
+
+    /// Average package power since the previous run, or over a short sampling
+    /// interval when it is missing or too old. `None` when there is no RAPL package zone
+    fn get_package_power(powercap: &Path) -> Result<Option<f64>, I3DisplayError> {
+        let samples = Self::get_energy_samples(powercap)?;
+        if samples.is_empty() {
+            return Ok(None);
+        }
+        let now = unix_timestamp_ms();
+
+        let state = StateFile::new(STATE_FILE_NAME)?;
+        let (previous_timestamp, previous_samples) = Self::parse_state(&state.read());
+        state.write(&Self::to_state(now, &samples))?;
+
+        let power = match now.saturating_sub(previous_timestamp) <= MAX_STATE_AGE_MS {
+            true => Self::compute_power(previous_timestamp, &previous_samples, now, &samples),
+            false => None,
+        };
+        match power {
+            Some(x) => Ok(Some(x)),
+            None => {
+                sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+                let new_samples = Self::get_energy_samples(powercap)?;
+                let new_now = unix_timestamp_ms();
+                state.write(&Self::to_state(new_now, &new_samples))?;
+                Ok(Self::compute_power(now, &samples, new_now, &new_samples))
+            }
+        }
+    }
+
+    /// Read energy counters of RAPL package zones (intel-rapl:0, intel-rapl:1...).
+    /// `energy_uj` is only readable by root since Linux 5.10, no zone is returned when
+    /// it can't be read so the battery is used instead
+    fn get_energy_samples(powercap: &Path) -> Result<Vec<EnergySample>, I3DisplayError> {
+        let read = |path: PathBuf| {
+            fs::read_to_string(&path).map_err(|e| {
+                I3DisplayError::from(format!("can't read file {}: {e}", path.display()))
+            })
+        };
+        let parse = |path: PathBuf, content: String| {
+            content
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| I3DisplayError::from(format!("can't parse {}: {e}", path.display())))
+        };
+
+        let mut zones = match fs::read_dir(powercap) {
+            Ok(x) => x
+                .filter_map(|x| x.ok())
+                .map(|x| x.path())
+                .collect::<Vec<_>>(),
+            Err(_) => return Ok(Vec::new()),
+        };
+        // intel-rapl-mmio zones duplicate the MSR ones with the same name, which come first
+        let is_mmio = |zone: &Path| {
+            zone.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .starts_with("intel-rapl-mmio")
+        };
+        zones.sort_by_key(|x| (is_mmio(x), x.clone()));
+
+        let mut names = Vec::new();
+        let mut samples = Vec::new();
+        for zone in zones {
+            let name = match read(zone.join("name")) {
+                Ok(x) if x.starts_with("package") => x.trim().to_string(),
+                _ => continue,
+            };
+            if names.contains(&name) {
+                continue;
+            }
+            let energy_path = zone.join("energy_uj");
+            let energy = match fs::read_to_string(&energy_path) {
+                Ok(x) => x,
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => return Ok(Vec::new()),
+                Err(e) => {
+                    return Err(I3DisplayError::from(format!(
+                        "can't read file {}: {e}",
+                        energy_path.display()
+                    )))
+                }
+            };
+            let max_energy_path = zone.join("max_energy_range_uj");
+            samples.push(EnergySample {
+                zone: zone
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                energy_uj: parse(energy_path, energy)?,
+                max_energy_uj: parse(max_energy_path.clone(), read(max_energy_path)?)?,
+            });
+            names.push(name);
+        }
+        samples.sort_by(|a, b| a.zone.cmp(&b.zone));
+        Ok(samples)
+    }
+
+    /// Sum of package zones power in watts, `None` if the zones differ
+    fn compute_power(
+        previous_timestamp: u64,
+        previous: &[EnergySample],
+        now: u64,
+        current: &[EnergySample],
+    ) -> Option<f64> {
+        if now <= previous_timestamp || previous.is_empty() || previous.len() != current.len() {
+            return None;
+        }
+
+        let mut energy_uj = 0;
+        for (previous, current) in previous.iter().zip(current) {
+            if previous.zone != current.zone {
+                return None;
+            }
+            // counters wrap around at max_energy_range_uj
+            energy_uj += match current.energy_uj >= previous.energy_uj {
+                true => current.energy_uj - previous.energy_uj,
+                false => current.max_energy_uj - previous.energy_uj + current.energy_uj,
+            };
+        }
+        Some(energy_uj as f64 / 1000.0 / (now - previous_timestamp) as f64)
+    }
+
+    /// State is the timestamp in ms on the first line, then `zone energy_uj max_energy_uj`
+    fn parse_state(content: &str) -> (u64, Vec<EnergySample>) {
+        let mut lines = content.lines();
+        let timestamp = lines
+            .next()
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(0);
+        let samples = lines
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                Some(EnergySample {
+                    zone: fields.next()?.to_string(),
+                    energy_uj: fields.next()?.parse().ok()?,
+                    max_energy_uj: fields.next()?.parse().ok()?,
+                })
+            })
+            .collect();
+        (timestamp, samples)
+    }
+
+    fn to_state(timestamp: u64, samples: &[EnergySample]) -> String {
+        let mut content = format!("{timestamp}\n");
+        for sample in samples {
+            content.push_str(&format!(
+                "{} {} {}\n",
+                sample.zone, sample.energy_uj, sample.max_energy_uj
+            ));
+        }
+        content
+    }
+
+    /// Discharge rate of all discharging batteries in watts, None when none reports it
+    fn get_battery_power(power_supply: &Path) -> Option<f64> {
+        let read = |path: PathBuf| fs::read_to_string(path).ok();
+        let parse = |path: PathBuf| read(path)?.trim().parse::<f64>().ok();
+
+        let mut watts = None;
+        for supply in fs::read_dir(power_supply).ok()?.filter_map(|x| x.ok()) {
+            let supply = supply.path();
+            if read(supply.join("type")).as_deref().map(str::trim) != Some("Battery")
+                || read(supply.join("status")).as_deref().map(str::trim) != Some("Discharging")
+            {
+                continue;
+            }
+            // some batteries only report current (µA) and voltage (µV), others nothing
+            let Some(micro_watts) = parse(supply.join("power_now")).or_else(|| {
+                Some(parse(supply.join("current_now"))? * parse(supply.join("voltage_now"))? / 1e6)
+            }) else {
+                continue;
+            };
+            watts = Some(watts.unwrap_or(0.0) + micro_watts / 1e6);
+        }
+        watts
+    }
+
+    fn i3blocks_print(&self) -> String {
+        [self.package_watts, self.battery_watts]
+            .iter()
+            .flatten()
+            .map(|x| format!("{x:.1}W"))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{EnergySample, PowerStats};
+    use crate::cmds::utils::write_files;
+
+    #[test]
+    fn test_power_package() {
+        let powercap = std::env::temp_dir().join(format!("powercap-{}", std::process::id()));
+        write_files(
+            &powercap,
+            &[
+                ("intel-rapl:0/name", "package-0\n"),
+                ("intel-rapl:0/energy_uj", "1000000\n"),
+                ("intel-rapl:0/max_energy_range_uj", "262143328850\n"),
+                ("intel-rapl:0:0/name", "core\n"),
+                ("intel-rapl:0:0/energy_uj", "500\n"),
+                ("intel-rapl:0:0/max_energy_range_uj", "262143328850\n"),
+                ("intel-rapl:1/name", "psys\n"),
+                // same package through MMIO, not counted twice
+                ("intel-rapl-mmio:0/name", "package-0\n"),
+                ("intel-rapl-mmio:0/energy_uj", "999999\n"),
+                ("intel-rapl-mmio:0/max_energy_range_uj", "262143328850\n"),
+            ],
+        );
+        let samples = PowerStats::get_energy_samples(&powercap).unwrap();
+        assert_eq!(
+            samples,
+            vec![EnergySample {
+                zone: "intel-rapl:0".to_string(),
+                energy_uj: 1000000,
+                max_energy_uj: 262143328850,
+            }]
+        );
+
+        let (timestamp, previous) = PowerStats::parse_state(&PowerStats::to_state(1000, &samples));
+        assert_eq!((timestamp, &previous), (1000, &samples));
+
+        // 10J in 2s
+        let current = [EnergySample {
+            zone: "intel-rapl:0".to_string(),
+            energy_uj: 11000000,
+            max_energy_uj: 262143328850,
+        }];
+        assert_eq!(
+            PowerStats::compute_power(timestamp, &previous, 3000, &current),
+            Some(5.0)
+        );
+        // counter wrapped around
+        let wrapped = [EnergySample {
+            zone: "intel-rapl:0".to_string(),
+            energy_uj: 1000000,
+            max_energy_uj: 2000000,
+        }];
+        let before_wrap = [EnergySample {
+            zone: "intel-rapl:0".to_string(),
+            energy_uj: 1500000,
+            max_energy_uj: 2000000,
+        }];
+        assert_eq!(
+            PowerStats::compute_power(0, &before_wrap, 1000, &wrapped),
+            Some(1.5)
+        );
+        assert_eq!(PowerStats::compute_power(0, &[], 1000, &wrapped), None);
+        assert_eq!(
+            PowerStats::compute_power(1000, &before_wrap, 1000, &wrapped),
+            None
+        );
+
+        fs::remove_dir_all(powercap).unwrap();
+    }
+
+    #[test]
+    fn test_power_battery() {
+        let power_supply =
+            std::env::temp_dir().join(format!("power-supply-{}", std::process::id()));
+        write_files(
+            &power_supply,
+            &[
+                ("AC/type", "Mains\n"),
+                ("BAT0/type", "Battery\n"),
+                ("BAT0/status", "Discharging\n"),
+                ("BAT0/power_now", "8100000\n"),
+                ("BAT1/type", "Battery\n"),
+                ("BAT1/status", "Discharging\n"),
+                ("BAT1/current_now", "500000\n"),
+                ("BAT1/voltage_now", "12000000\n"),
+                ("BAT2/type", "Battery\n"),
+                ("BAT2/status", "Discharging\n"),
+            ],
+        );
+        assert_eq!(PowerStats::get_battery_power(&power_supply), Some(14.1));
+
+        write_files(&power_supply, &[("BAT0/status", "Charging\n")]);
+        write_files(&power_supply, &[("BAT1/status", "Full\n")]);
+        // the discharging battery doesn't report its power
+        assert_eq!(PowerStats::get_battery_power(&power_supply), None);
+
+        let power_stats = PowerStats {
+            package_watts: Some(12.34),
+            battery_watts: None,
+        };
+        assert_eq!(power_stats.i3blocks_print(), "12.3W");
+
+        assert_eq!(PowerStats::danger_threshold(25, 45), 35);
+        assert_eq!(PowerStats::danger_threshold(100, 200), 150);
+        assert_eq!(PowerStats::danger_threshold(255, 255), 255);
+
+        fs::remove_dir_all(power_supply).unwrap();
+    }
+}
@@ -0,0 +1,591 @@
+use std::{fmt::Display, mem::MaybeUninit};
+
+use clap::{Args, ValueEnum};
+use compound_duration::format_dhms;
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    StatusCode,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState};
+
+use super::{
+    moonraker::Moonraker, octoprint::Octoprint, prusa_link::PrusaLink, state::unix_timestamp,
+    utils::http_client,
+};
+
+#[derive(Args)]
+pub struct PrinterArgs {
+    #[arg(short, long)]
+    pub backend: PrinterBackend,
+    #[arg(short, long)]
+    pub url: String,
+    // X-Api-Key header: OctoPrint and Moonraker api key, PrusaLink token
+    #[arg(short, long, visible_alias = "token")]
+    pub apikey: Option<String>,
+    // PrusaLink digest authentication, when no token is given
+    #[arg(short, long)]
+    pub login: Option<String>,
+    #[arg(short, long)]
+    pub password: Option<String>,
+    #[command(flatten)]
+    pub display: PrinterDisplayArgs,
+}
+
+/// Display options shared by every printer command
+#[derive(Args)]
+pub struct PrinterDisplayArgs {
+    #[arg(short = 'r', long, default_value_t = false)]
+    pub hide_remaining_time: bool,
+    #[arg(long, default_value = "duration")]
+    pub eta_format: EtaFormat,
+    // extra fields after the state and progress
+    #[arg(short = 'd', long = "display", value_delimiter = ',')]
+    pub fields: Vec<PrinterField>,
+    // °C away from the target before warning while printing
+    #[arg(long, default_value_t = 5.0)]
+    pub temperature_deviation: f64,
+    // longer file names are cut with `…`
+    #[arg(long, default_value_t = 20)]
+    pub file_length: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum EtaFormat {
+    // remaining time, `4h12m`
+    Duration,
+    // local completion time, `done 03:40` or `tomorrow 09:15`
+    Clock,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum PrinterField {
+    // nozzle and bed, `current/target°`
+    Temperatures,
+    File,
+    Elapsed,
+    // used so far
+    Filament,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PrinterBackend {
+    Octoprint,
+    PrusaLink,
+    Moonraker,
+}
+
+/// A printer API, queried for its current state. Data only needed by some `fields`
+/// can be left out when they aren't displayed, to save requests
+pub trait Printer {
+    fn status(&self, client: &Client, fields: &[PrinterField])
+        -> Result<PrinterInfo, PrinterError>;
+}
+
+/// Backend independent printer state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrinterState {
+    Idle,
+    Busy,
+    Printing,
+    Paused,
+    Finished,
+    Stopped,
+    Attention,
+    Offline,
+}
+
+impl Display for PrinterState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrinterState::Idle => write!(f, "󰐫 󰒲"),
+            PrinterState::Busy => write!(f, "󱢹"),
+            PrinterState::Printing => write!(f, "󰹛"),
+            PrinterState::Paused => write!(f, "󰐫 "),
+            PrinterState::Finished => write!(f, "󰐫 "),
+            PrinterState::Stopped => write!(f, "󰐫 "),
+            PrinterState::Attention => write!(f, "󱇁"),
+            PrinterState::Offline => write!(f, "Offline"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Temperature {
+    pub current: f64,
+    // 0 when the heater is off
+    pub target: f64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PrinterInfo {
+    pub state: PrinterState,
+    // percent
+    pub completion: f64,
+    // seconds
+    pub remaining_time: i64,
+    pub nozzle: Option<Temperature>,
+    pub bed: Option<Temperature>,
+    // job details, `None` without a job
+    pub file: Option<String>,
+    // seconds
+    pub elapsed_time: Option<i64>,
+    pub filament: Option<Filament>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Filament {
+    pub length_mm: f64,
+    pub volume_cm3: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum PrinterError {
+    InvalidCredentials(String),
+    InvalidConnection(String),
+    ConnectionTimeout,
+    ConnectionRefused,
+    InvalidResponse(String),
+    DeserializationError(String),
+}
+
+impl Display for PrinterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrinterError::InvalidCredentials(x) => write!(f, "Invalid credentials: {x}"),
+            PrinterError::InvalidConnection(x) => write!(f, "Invalid connection: {x}"),
+            PrinterError::ConnectionTimeout => write!(f, "Connection timeout"),
+            PrinterError::ConnectionRefused => write!(f, "Connection refused"),
+            PrinterError::InvalidResponse(x) => write!(f, "Invalid response: {x}"),
+            PrinterError::DeserializationError(x) => write!(f, "Deserialization error: {x}"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for PrinterError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            PrinterError::ConnectionTimeout
+        } else if e.is_connect() {
+            PrinterError::ConnectionRefused
+        } else {
+            PrinterError::InvalidResponse(e.to_string())
+        }
+    }
+}
+
+pub struct PrinterStatus;
+
+impl CommandStatus<PrinterArgs> for PrinterStatus {
+    fn get(command: &PrinterArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let url = command.url.clone();
+        let apikey = command.apikey.clone();
+        match command.backend {
+            PrinterBackend::Octoprint => display(&Octoprint { url, apikey }, &command.display),
+            PrinterBackend::PrusaLink => display(
+                &PrusaLink {
+                    url,
+                    token: apikey,
+                    login: command.login.clone(),
+                    password: command.password.clone(),
+                },
+                &command.display,
+            ),
+            PrinterBackend::Moonraker => display(&Moonraker { url, apikey }, &command.display),
+        }
+    }
+}
+
+/// Query the printer and render its state, nothing is shown when it's unreachable
+pub fn display(
+    printer: &impl Printer,
+    display: &PrinterDisplayArgs,
+) -> Result<Option<I3Display>, I3DisplayError> {
+    let client = client().map_err(|e| I3DisplayError::from(e.to_string()))?;
+    let info = match printer.status(&client, &display.fields) {
+        Ok(x) => x,
+        Err(PrinterError::ConnectionRefused | PrinterError::ConnectionTimeout) => return Ok(None),
+        Err(e) => return Err(I3DisplayError::from(format!("Error: {e}"))),
+    };
+    let (long_line, short_line, color) = info.i3blocks_print(display);
+    Ok(Some(I3Display::new(None, long_line, short_line, color)))
+}
+
+pub fn client() -> Result<Client, PrinterError> {
+    http_client(std::time::Duration::from_secs(1), false)
+        .map_err(|e| PrinterError::InvalidConnection(e.message))
+}
+
+/// GET request with the optional api key header
+pub fn get(client: &Client, url: &str, apikey: &Option<String>) -> RequestBuilder {
+    let request = client.get(url);
+    match apikey {
+        Some(x) => request.header("X-Api-Key", x),
+        None => request,
+    }
+}
+
+/// Map the HTTP status, then deserialize the body
+pub fn parse_response<T: DeserializeOwned>(res: Response) -> Result<T, PrinterError> {
+    match res.status() {
+        StatusCode::OK => res
+            .json()
+            .map_err(|e| PrinterError::DeserializationError(e.to_string())),
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => {
+            Err(PrinterError::ConnectionTimeout)
+        }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(PrinterError::InvalidCredentials(
+            "Connection forbidden: invalid api key?".to_string(),
+        )),
+        x => Err(PrinterError::InvalidResponse(format!("Error: {x}"))),
+    }
+}
+
+impl Temperature {
+    /// From API values, no temperature without a current value
+    pub fn new(current: Option<f64>, target: Option<f64>) -> Option<Self> {
+        Some(Temperature {
+            current: current?,
+            target: target.unwrap_or(0.0),
+        })
+    }
+
+    fn is_off_target(&self, deviation: f64) -> bool {
+        self.target > 0.0 && (self.current - self.target).abs() > deviation
+    }
+
+    /// `215/220°`, with an arrow while heating up, `98°` when the heater is off
+    fn i3blocks_print(&self, deviation: f64) -> String {
+        if self.target <= 0.0 {
+            return format!("{:.0}°", self.current);
+        }
+        let heating = match self.current < self.target - deviation {
+            true => "↑",
+            false => "",
+        };
+        format!("{:.0}/{:.0}°{heating}", self.current, self.target)
+    }
+}
+
+impl Filament {
+    fn i3blocks_print(&self) -> String {
+        let length = format!("{:.2}m", self.length_mm / 1000.0);
+        match self.volume_cm3 {
+            Some(x) => format!("{length} {x:.1}cm³"),
+            None => length,
+        }
+    }
+}
+
+impl PrinterInfo {
+    /// The short line only has the state and progress, selected fields are added to
+    /// the long line in the given order
+    fn i3blocks_print(
+        &self,
+        display: &PrinterDisplayArgs,
+    ) -> (String, String, Option<I3StatusRustColorState>) {
+        let (short_line, mut color) = match self.state {
+            PrinterState::Printing => (format!("{} {:.1}%", self.state, self.completion), None),
+            PrinterState::Attention => (
+                self.state.to_string(),
+                Some(I3StatusRustColorState::I3StatusRustStateWarning),
+            ),
+            PrinterState::Finished => (
+                self.state.to_string(),
+                Some(I3StatusRustColorState::I3StatusRustStateGood),
+            ),
+            _ => (self.state.to_string(), None),
+        };
+
+        let mut long_line = vec![short_line.clone()];
+        if self.state == PrinterState::Printing && !display.hide_remaining_time {
+            long_line.push(match display.eta_format {
+                EtaFormat::Duration => format_dhms(self.remaining_time as usize),
+                EtaFormat::Clock => {
+                    let now = unix_timestamp() as i64;
+                    match (
+                        LocalTime::new(now),
+                        LocalTime::new(now + self.remaining_time),
+                    ) {
+                        (Some(now), Some(eta)) => eta.i3blocks_print(&now),
+                        _ => format_dhms(self.remaining_time as usize),
+                    }
+                }
+            });
+        }
+        let deviation = display.temperature_deviation;
+        let heaters = [self.nozzle, self.bed].into_iter().flatten();
+        for field in &display.fields {
+            match field {
+                PrinterField::Temperatures => {
+                    long_line.extend(heaters.clone().map(|x| x.i3blocks_print(deviation)))
+                }
+                PrinterField::File => long_line.extend(
+                    self.file
+                        .as_deref()
+                        .map(|x| truncate(x, display.file_length)),
+                ),
+                PrinterField::Elapsed => long_line.extend(
+                    self.elapsed_time
+                        .map(|x| format!("elapsed {}", format_dhms(x as usize))),
+                ),
+                PrinterField::Filament => {
+                    long_line.extend(self.filament.map(|x| x.i3blocks_print()))
+                }
+            }
+        }
+
+        if display.fields.contains(&PrinterField::Temperatures)
+            && self.state == PrinterState::Printing
+            && heaters.clone().any(|x| x.is_off_target(deviation))
+        {
+            color = color.max(Some(I3StatusRustColorState::I3StatusRustStateWarning));
+        }
+        (long_line.join(" "), short_line, color)
+    }
+}
+
+/// Broken down local time, from `localtime_r`
+#[derive(Debug, PartialEq)]
+struct LocalTime {
+    year: i32,
+    // 0-365
+    year_day: i32,
+    // 1-12
+    month: i32,
+    day: i32,
+    hour: i32,
+    minute: i32,
+}
+
+impl LocalTime {
+    fn new(timestamp: i64) -> Option<Self> {
+        let mut tm = MaybeUninit::<libc::tm>::uninit();
+        // SAFETY: tm is only read when localtime_r succeeds
+        let tm = unsafe {
+            if libc::localtime_r(&(timestamp as libc::time_t), tm.as_mut_ptr()).is_null() {
+                return None;
+            }
+            tm.assume_init()
+        };
+        Some(LocalTime {
+            year: tm.tm_year + 1900,
+            year_day: tm.tm_yday,
+            month: tm.tm_mon + 1,
+            day: tm.tm_mday,
+            hour: tm.tm_hour,
+            minute: tm.tm_min,
+        })
+    }
+
+    fn is_next_day_of(&self, other: &LocalTime) -> bool {
+        match self.year - other.year {
+            0 => self.year_day == other.year_day + 1,
+            // from the 31st of December
+            1 => self.year_day == 0 && other.month == 12 && other.day == 31,
+            _ => false,
+        }
+    }
+
+    /// Completion time relative to `now`: `done 03:40`, `tomorrow 09:15`, `10-21 09:15`
+    fn i3blocks_print(&self, now: &LocalTime) -> String {
+        let time = format!("{:02}:{:02}", self.hour, self.minute);
+        if self.year == now.year && self.year_day == now.year_day {
+            format!("done {time}")
+        } else if self.is_next_day_of(now) {
+            format!("tomorrow {time}")
+        } else {
+            format!("{:02}-{:02} {time}", self.month, self.day)
+        }
+    }
+}
+
+fn truncate(text: &str, length: usize) -> String {
+    match text.chars().count() > length {
+        true => format!(
+            "{}…",
+            text.chars()
+                .take(length.saturating_sub(1))
+                .collect::<String>()
+        ),
+        false => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::I3StatusRustColorState;
+
+    use super::{
+        truncate, EtaFormat, Filament, LocalTime, PrinterDisplayArgs, PrinterField, PrinterInfo,
+        PrinterState, Temperature,
+    };
+
+    #[test]
+    fn test_printer_print() {
+        let mut info = PrinterInfo {
+            state: PrinterState::Printing,
+            completion: 62.0,
+            remaining_time: 34320,
+            nozzle: Some(Temperature {
+                current: 215.4,
+                target: 220.0,
+            }),
+            bed: Some(Temperature {
+                current: 60.0,
+                target: 60.0,
+            }),
+            file: None,
+            elapsed_time: None,
+            filament: None,
+        };
+        let mut display = PrinterDisplayArgs {
+            hide_remaining_time: false,
+            eta_format: EtaFormat::Duration,
+            fields: Vec::new(),
+            temperature_deviation: 5.0,
+            file_length: 20,
+        };
+        let (line, _, color) = info.i3blocks_print(&display);
+        assert_eq!(line, "󰹛 62.0% 9h32m");
+        assert!(color.is_none());
+        display.hide_remaining_time = true;
+        assert_eq!(info.i3blocks_print(&display).0, "󰹛 62.0%");
+
+        info.state = PrinterState::Finished;
+        let (line, _, color) = info.i3blocks_print(&display);
+        assert_eq!(line, "󰐫 ");
+        assert!(color == Some(I3StatusRustColorState::I3StatusRustStateGood));
+        info.state = PrinterState::Attention;
+        assert!(
+            info.i3blocks_print(&display).2
+                == Some(I3StatusRustColorState::I3StatusRustStateWarning)
+        );
+    }
+
+    #[test]
+    fn test_printer_temperatures() {
+        let mut info = PrinterInfo {
+            state: PrinterState::Printing,
+            completion: 10.0,
+            remaining_time: 3600,
+            nozzle: Some(Temperature {
+                current: 215.4,
+                target: 220.0,
+            }),
+            bed: Some(Temperature {
+                current: 60.0,
+                target: 60.0,
+            }),
+            file: None,
+            elapsed_time: None,
+            filament: None,
+        };
+        let display = PrinterDisplayArgs {
+            hide_remaining_time: true,
+            eta_format: EtaFormat::Duration,
+            fields: vec![PrinterField::Temperatures],
+            temperature_deviation: 5.0,
+            file_length: 20,
+        };
+        let (line, _, color) = info.i3blocks_print(&display);
+        assert_eq!(line, "󰹛 10.0% 215/220° 60/60°");
+        assert!(color.is_none());
+
+        // heating up while printing
+        info.nozzle = Some(Temperature {
+            current: 150.0,
+            target: 220.0,
+        });
+        let (line, _, color) = info.i3blocks_print(&display);
+        assert_eq!(line, "󰹛 10.0% 150/220°↑ 60/60°");
+        assert!(color == Some(I3StatusRustColorState::I3StatusRustStateWarning));
+
+        // heaters off, only the current temperature
+        info.state = PrinterState::Idle;
+        info.nozzle = Some(Temperature {
+            current: 98.3,
+            target: 0.0,
+        });
+        info.bed = None;
+        let (line, _, color) = info.i3blocks_print(&display);
+        assert_eq!(line, "󰐫 󰒲 98°");
+        assert!(color.is_none());
+    }
+
+    #[test]
+    fn test_printer_job_details() {
+        let info = PrinterInfo {
+            state: PrinterState::Printing,
+            completion: 62.0,
+            remaining_time: 34320,
+            nozzle: None,
+            bed: None,
+            file: Some("3DBenchy_0.2mm_PLA_MK4_1h2m.bgcode".to_string()),
+            elapsed_time: Some(59544),
+            filament: Some(Filament {
+                length_mm: 1416.72,
+                volume_cm3: Some(3.4),
+            }),
+        };
+        let display = PrinterDisplayArgs {
+            hide_remaining_time: false,
+            eta_format: EtaFormat::Duration,
+            fields: vec![
+                PrinterField::File,
+                PrinterField::Elapsed,
+                PrinterField::Filament,
+            ],
+            temperature_deviation: 5.0,
+            file_length: 20,
+        };
+        let (long_line, short_line, _) = info.i3blocks_print(&display);
+        assert_eq!(
+            long_line,
+            "󰹛 62.0% 9h32m 3DBenchy_0.2mm_PLA_… elapsed 16h32m24s 1.42m 3.4cm³"
+        );
+        assert_eq!(short_line, "󰹛 62.0%");
+
+        assert_eq!(truncate("benchy.gcode", 12), "benchy.gcode");
+        assert_eq!(truncate("benchy.gcode", 7), "benchy…");
+    }
+
+    #[test]
+    fn test_printer_eta_clock() {
+        let local_time = |year, year_day, month, day, hour, minute| LocalTime {
+            year,
+            year_day,
+            month,
+            day,
+            hour,
+            minute,
+        };
+        let now = local_time(2023, 293, 10, 21, 23, 10);
+        assert_eq!(
+            local_time(2023, 293, 10, 21, 23, 40).i3blocks_print(&now),
+            "done 23:40"
+        );
+        assert_eq!(
+            local_time(2023, 294, 10, 22, 9, 15).i3blocks_print(&now),
+            "tomorrow 09:15"
+        );
+        assert_eq!(
+            local_time(2023, 295, 10, 23, 3, 5).i3blocks_print(&now),
+            "10-23 03:05"
+        );
+
+        let now = local_time(2023, 364, 12, 31, 22, 0);
+        assert_eq!(
+            local_time(2024, 0, 1, 1, 3, 40).i3blocks_print(&now),
+            "tomorrow 03:40"
+        );
+
+        // same day and time whatever the timezone
+        let x = LocalTime::new(1698000000).unwrap();
+        assert_eq!(
+            LocalTime::new(1698000000 + 59).unwrap().i3blocks_print(&x),
+            format!("done {:02}:{:02}", x.hour, x.minute)
+        );
+    }
+}
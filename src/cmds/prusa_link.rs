@@ -1,13 +1,14 @@
-use std::fmt::Display;
-
 use clap::Args;
-use compound_duration::format_dhms;
 use diqwest::blocking::WithDigestAuth;
-use reqwest::blocking::Response;
-use reqwest::StatusCode;
+use reqwest::blocking::{Client, Response};
 use serde::Deserialize;
 
-use crate::{CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState};
+use crate::{CommandStatus, I3Display, I3DisplayError};
+
+use super::printer::{
+    self, Printer, PrinterDisplayArgs, PrinterError, PrinterField, PrinterInfo, PrinterState,
+    Temperature,
+};
 
 #[derive(Args)]
 pub struct PrusaLinkArgs {
@@ -19,8 +20,8 @@ pub struct PrusaLinkArgs {
     pub password: Option<String>,
     #[arg(short, long)]
     pub token: Option<String>,
-    #[arg(short = 'r', long, default_value_t = false)]
-    pub hide_remaining_time: bool,
+    #[command(flatten)]
+    pub display: PrinterDisplayArgs,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -36,9 +37,25 @@ pub struct PrusaLinkJob {
     pub time_remaining: i64,
 }
 
+// api/v1/job, only for the file name
+#[derive(Debug, Deserialize, Clone)]
+pub struct PrusaLinkJobDetails {
+    pub file: Option<PrusaLinkFile>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PrusaLinkFile {
+    pub name: String,
+    pub display_name: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct PrusaPrinter {
     pub state: PrusaPrintState,
+    pub temp_nozzle: Option<f64>,
+    pub target_nozzle: Option<f64>,
+    pub temp_bed: Option<f64>,
+    pub target_bed: Option<f64>,
 }
 
 // https://github.com/prusa3d/Prusa-Link/blob/583f3b613170ed05ede1673a952b5fb577c1cdcb/prusa/link/const.py#L43
@@ -55,189 +72,113 @@ pub enum PrusaPrintState {
     Attention,
 }
 
-impl Display for PrusaPrintState {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            PrusaPrintState::Printing => write!(f, "󰹛"),
-            PrusaPrintState::Paused => write!(f, "󰐫 "),
-            PrusaPrintState::Finished => write!(f, "󰐫 "),
-            PrusaPrintState::Stopped => write!(f, "󰐫 "),
-            PrusaPrintState::Idle => write!(f, "󰐫 󰒲"),
-            PrusaPrintState::Busy => write!(f, "󱢹"),
-            PrusaPrintState::Ready => write!(f, "󰐫 󰒲"),
-            PrusaPrintState::Attention => write!(f, "󱇁"),
+impl From<PrusaPrintState> for PrinterState {
+    fn from(state: PrusaPrintState) -> Self {
+        match state {
+            PrusaPrintState::Printing => PrinterState::Printing,
+            PrusaPrintState::Paused => PrinterState::Paused,
+            PrusaPrintState::Finished => PrinterState::Finished,
+            PrusaPrintState::Stopped => PrinterState::Stopped,
+            PrusaPrintState::Idle | PrusaPrintState::Ready => PrinterState::Idle,
+            PrusaPrintState::Busy => PrinterState::Busy,
+            PrusaPrintState::Attention => PrinterState::Attention,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct PrusaLinkStatus {
-    status: PrusaPrintState,
-    remaining_time: i64,
-    completion: f64,
-}
-
-pub enum PrusaLinkError {
-    InvalidCredentials,
-    InvalidConnection(String),
-    ConnectionTimeout,
-    ConnectionRefused,
-    InvalidResponse(String),
-    DeserializationError(String),
+/// Authenticated with the token, or with login/password (digest) when there is none
+pub struct PrusaLink {
+    pub url: String,
+    pub token: Option<String>,
+    pub login: Option<String>,
+    pub password: Option<String>,
 }
 
-impl Display for PrusaLinkError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            PrusaLinkError::InvalidCredentials => {
-                write!(f, "No token or login/password provided")
-            }
-            PrusaLinkError::InvalidConnection(x) => write!(f, "Invalid connection: {x}"),
-            PrusaLinkError::ConnectionTimeout => write!(f, "Connection timeout"),
-            PrusaLinkError::ConnectionRefused => write!(f, "Connection refused"),
-            PrusaLinkError::InvalidResponse(x) => write!(f, "Invalid response: {}", x),
-            PrusaLinkError::DeserializationError(x) => write!(f, "Deserialization error: {}", x),
+impl Printer for PrusaLink {
+    fn status(
+        &self,
+        client: &Client,
+        fields: &[PrinterField],
+    ) -> Result<PrinterInfo, PrinterError> {
+        let content: PrusaLinkJobResponse =
+            printer::parse_response(self.send(client, "/api/v1/status")?)?;
+        let job = content.job.is_some();
+        let mut info = Self::to_printer_info(content);
+        if job && fields.contains(&PrinterField::File) {
+            info.file = self.job_file(client);
         }
+        Ok(info)
     }
 }
 
-impl CommandStatus<PrusaLinkArgs> for PrusaLinkStatus {
-    fn get(command: &PrusaLinkArgs) -> Result<Option<I3Display>, I3DisplayError> {
-        // basic check
-        if command.token.is_none() && (command.login.is_none() || command.password.is_none()) {
-            return Err(I3DisplayError {
-                message: PrusaLinkError::InvalidCredentials.to_string(),
-            });
+impl PrusaLink {
+    fn send(&self, client: &Client, path: &str) -> Result<Response, PrinterError> {
+        let request_url = format!("{}{path}", self.url);
+        match (&self.token, &self.login, &self.password) {
+            (Some(_), _, _) => Ok(printer::get(client, &request_url, &self.token).send()?),
+            (None, Some(login), Some(password)) => client
+                .get(request_url)
+                .send_with_digest_auth(login, password)
+                .map_err(|e| match e {
+                    diqwest::error::Error::Reqwest(e) => PrinterError::from(e),
+                    e => PrinterError::InvalidCredentials(e.to_string()),
+                }),
+            _ => Err(PrinterError::InvalidCredentials(
+                "No token or login/password provided".to_string(),
+            )),
         }
-        let res = match Self::get_job_status(
-            &command.url,
-            &command.token,
-            &command.login,
-            &command.password,
-        ) {
-            Ok(x) => x,
-            Err(e) => match e {
-                PrusaLinkError::ConnectionRefused | PrusaLinkError::ConnectionTimeout => {
-                    return Ok(None)
-                }
-                _ => return Err(I3DisplayError::from(e.to_string())),
-            },
-        };
-        let prusa_link_status = Self::to_prusa_link_status(res)
-            .map_err(|e| I3DisplayError::from(format!("Error: {}", e)))?;
-
-        let (line, color) = match prusa_link_status.status {
-            PrusaPrintState::Printing => {
-                let mut x = format!(
-                    "{} {:.1}%",
-                    prusa_link_status.status, prusa_link_status.completion
-                );
-                if !command.hide_remaining_time {
-                    x = format!(
-                        "{} {}",
-                        x,
-                        format_dhms(prusa_link_status.remaining_time as usize)
-                    );
-                }
-                (x, None)
-            }
-            PrusaPrintState::Attention => (
-                prusa_link_status.status.to_string(),
-                Some(I3StatusRustColorState::I3StatusRustStateWarning),
-            ),
-            PrusaPrintState::Finished => (
-                prusa_link_status.status.to_string(),
-                Some(I3StatusRustColorState::I3StatusRustStateGood),
-            ),
-            _ => (prusa_link_status.status.to_string(), None),
-        };
-
-        Ok(Some(I3Display::new(None, line.clone(), line, color)))
     }
-}
 
-impl PrusaLinkStatus {
-    fn get_job_status(
-        url: &String,
-        token: &Option<String>,
-        login: &Option<String>,
-        password: &Option<String>,
-    ) -> Result<Response, PrusaLinkError> {
-        // url
-        let request_url = format!("{url}/api/v1/status");
-
-        let client = reqwest::blocking::Client::builder()
-            // .default_headers(headers)
-            .timeout(std::time::Duration::from_secs(1))
-            .build()
-            .map_err(|e| PrusaLinkError::InvalidConnection(e.to_string()))?;
+    /// The status has no file name, it's left out when the job can't be read
+    fn job_file(&self, client: &Client) -> Option<String> {
+        let res = self.send(client, "/api/v1/job").ok()?;
+        let file = printer::parse_response::<PrusaLinkJobDetails>(res)
+            .ok()?
+            .file?;
+        Some(file.display_name.unwrap_or(file.name))
+    }
 
-        match token {
-            Some(x) => client
-                .get(request_url)
-                .header("X-Api-Key", x)
-                .send()
-                .map_err(|e| {
-                    if e.is_timeout() {
-                        PrusaLinkError::ConnectionTimeout
-                    } else if e.is_connect() {
-                        PrusaLinkError::ConnectionRefused
-                    } else {
-                        PrusaLinkError::InvalidResponse(e.to_string())
-                    }
-                }),
-            None => client
-                .get(request_url)
-                .send_with_digest_auth(
-                    login.clone().unwrap().as_str(),
-                    password.clone().unwrap().as_str(),
-                )
-                .map_err(|e| PrusaLinkError::InvalidResponse(e.to_string())),
+    fn to_printer_info(content: PrusaLinkJobResponse) -> PrinterInfo {
+        let printer = content.printer;
+        let state = PrinterState::from(printer.state);
+        PrinterInfo {
+            nozzle: Temperature::new(printer.temp_nozzle, printer.target_nozzle),
+            bed: Temperature::new(printer.temp_bed, printer.target_bed),
+            completion: match (state, &content.job) {
+                (PrinterState::Finished, _) => 100.0,
+                (PrinterState::Idle | PrinterState::Busy, _) => 0.0,
+                (_, Some(x)) => x.progress,
+                (_, None) => 0.0,
+            },
+            remaining_time: content.job.as_ref().map(|x| x.time_remaining).unwrap_or(0),
+            elapsed_time: content.job.map(|x| x.time_printing),
+            file: None,
+            filament: None,
+            state,
         }
     }
+}
 
-    fn to_prusa_link_status(res: Response) -> Result<PrusaLinkStatus, PrusaLinkError> {
-        let content = match res.status() {
-            StatusCode::OK => {
-                let x: PrusaLinkJobResponse = res
-                    .json()
-                    .map_err(|e| PrusaLinkError::DeserializationError(e.to_string()))?;
-                x
-            }
-            StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => {
-                return Err(PrusaLinkError::ConnectionTimeout)
-            }
-            StatusCode::FORBIDDEN => return Err(PrusaLinkError::InvalidCredentials),
-            _ => {
-                return Err(PrusaLinkError::InvalidResponse(format!(
-                    "Error: {}",
-                    res.status()
-                )))
-            }
-        };
+pub struct PrusaLinkStatus;
 
-        Ok(PrusaLinkStatus {
-            status: content.printer.state.clone(),
-            remaining_time: match content.job.clone() {
-                Some(x) => x.time_remaining,
-                None => 0,
-            },
-            completion: match content.printer.state {
-                PrusaPrintState::Printing
-                | PrusaPrintState::Paused
-                | PrusaPrintState::Stopped
-                | PrusaPrintState::Attention => match content.job {
-                    Some(x) => x.progress,
-                    None => return Err(PrusaLinkError::InvalidResponse(format!("{:?}", content))),
-                },
-                PrusaPrintState::Idle | PrusaPrintState::Busy | PrusaPrintState::Ready => 0.0,
-                PrusaPrintState::Finished => 100.0,
-            },
-        })
+impl CommandStatus<PrusaLinkArgs> for PrusaLinkStatus {
+    fn get(command: &PrusaLinkArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let prusa_link = PrusaLink {
+            url: command.url.clone(),
+            token: command.token.clone(),
+            login: command.login.clone(),
+            password: command.password.clone(),
+        };
+        printer::display(&prusa_link, &command.display)
     }
 }
 
-pub mod tests {
+#[cfg(test)]
+mod tests {
+    use crate::cmds::printer::{PrinterInfo, PrinterState, Temperature};
+
+    use super::{PrusaLink, PrusaLinkJobDetails, PrusaLinkJobResponse};
+
     #[test]
     fn test_prusa_link() {
         // api/v1/status
@@ -293,22 +234,72 @@ pub mod tests {
         "#;
 
         let api_result_stopped = r#"
-        { "printer": { "state": "StOPPED" } }}
+        { "printer": { "state": "STOPPED" } }
         "#;
 
-        let printing = serde_json::from_str::<crate::cmds::prusa_link::PrusaLinkJobResponse>(
-            api_result_printing,
-        );
+        let printing = serde_json::from_str::<PrusaLinkJobResponse>(api_result_printing);
         assert!(printing.is_ok());
 
-        let finished = serde_json::from_str::<crate::cmds::prusa_link::PrusaLinkJobResponse>(
-            api_result_finished,
-        );
+        let finished = serde_json::from_str::<PrusaLinkJobResponse>(api_result_finished);
         assert!(finished.is_ok());
 
-        let stopped = serde_json::from_str::<crate::cmds::prusa_link::PrusaLinkJobResponse>(
-            api_result_stopped,
-        );
+        let stopped = serde_json::from_str::<PrusaLinkJobResponse>(api_result_stopped);
         assert!(stopped.is_ok());
+
+        assert_eq!(
+            PrusaLink::to_printer_info(printing.unwrap()),
+            PrinterInfo {
+                state: PrinterState::Printing,
+                completion: 62.0,
+                remaining_time: 34320,
+                nozzle: Some(Temperature {
+                    current: 219.2,
+                    target: 220.0
+                }),
+                bed: Some(Temperature {
+                    current: 60.0,
+                    target: 60.0
+                }),
+                file: None,
+                elapsed_time: Some(59544),
+                filament: None,
+            }
+        );
+        assert_eq!(
+            PrusaLink::to_printer_info(finished.unwrap()).completion,
+            100.0
+        );
+        assert_eq!(
+            PrusaLink::to_printer_info(stopped.unwrap()).state,
+            PrinterState::Stopped
+        );
+    }
+
+    #[test]
+    fn test_prusa_link_job() {
+        // api/v1/job
+        let api_result_job = r#"
+        {
+            "id": 12,
+            "state": "PRINTING",
+            "progress": 62.00,
+            "time_remaining": 34320,
+            "time_printing": 59544,
+            "file": {
+                "refs": { "download": "/usb/3DBENC~1.BGC" },
+                "name": "3DBENC~1.BGC",
+                "display_name": "3DBenchy_0.2mm_PLA_MK4_1h2m.bgcode",
+                "path": "/usb",
+                "size": 1066284,
+                "m_timestamp": 1698325380
+            }
+        }
+        "#;
+        let job = serde_json::from_str::<PrusaLinkJobDetails>(api_result_job).unwrap();
+        let file = job.file.unwrap();
+        assert_eq!(
+            file.display_name,
+            Some("3DBenchy_0.2mm_PLA_MK4_1h2m.bgcode".to_string())
+        );
     }
 }
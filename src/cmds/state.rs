@@ -0,0 +1,85 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::I3DisplayError;
+
+const STATE_DIR_NAME: &str = "i3-status-info";
+
+/// Small file kept between runs, for blocks needing previous samples
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    /// State files live in `$XDG_STATE_HOME/i3-status-info` (`~/.local/state` by default)
+    pub fn new(name: &str) -> Result<Self, I3DisplayError> {
+        let state_home = match env::var_os("XDG_STATE_HOME") {
+            Some(x) if !x.is_empty() => PathBuf::from(x),
+            _ => match env::var_os("HOME") {
+                Some(x) => PathBuf::from(x).join(".local/state"),
+                None => {
+                    return Err(I3DisplayError::from(
+                        "can't find state directory: HOME is not set".to_string(),
+                    ))
+                }
+            },
+        };
+        Self::in_dir(&state_home.join(STATE_DIR_NAME), name)
+    }
+
+    /// State file in a given directory, created if needed
+    pub fn in_dir(state_dir: &Path, name: &str) -> Result<Self, I3DisplayError> {
+        fs::create_dir_all(state_dir).map_err(|e| {
+            I3DisplayError::from(format!("can't create {}: {e}", state_dir.display()))
+        })?;
+
+        Ok(Self {
+            path: state_dir.join(name),
+        })
+    }
+
+    /// Previous content, empty on first run
+    pub fn read(&self) -> String {
+        fs::read_to_string(&self.path).unwrap_or_default()
+    }
+
+    pub fn write(&self, content: &str) -> Result<(), I3DisplayError> {
+        fs::write(&self.path, content).map_err(|e| {
+            I3DisplayError::from(format!("can't write file {}: {e}", self.path.display()))
+        })
+    }
+}
+
+pub fn unix_timestamp() -> u64 {
+    unix_timestamp_ms() / 1000
+}
+
+pub fn unix_timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateFile;
+
+    #[test]
+    fn test_state_file() {
+        let state_dir = std::env::temp_dir().join(format!("state-{}", std::process::id()));
+
+        let state = StateFile::in_dir(&state_dir, "test").unwrap();
+        assert_eq!(state.read(), "");
+        state.write("1 2\n").unwrap();
+        assert_eq!(
+            StateFile::in_dir(&state_dir, "test").unwrap().read(),
+            "1 2\n"
+        );
+
+        std::fs::remove_dir_all(state_dir).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use clap::{Args, ValueEnum};
+use zbus::{blocking::Connection, zvariant::OwnedObjectPath};
+
+use crate::{CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState};
+
+const SYSTEMD_DESTINATION: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const SYSTEMD_MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+
+#[derive(Args)]
+pub struct SystemdArgs {
+    #[arg(short, long, default_value = "all")]
+    pub scope: SystemdScope,
+    #[arg(short, long, default_value_t = false)]
+    pub list: bool,
+    #[arg(short, long, value_delimiter = ',')]
+    pub ignore: Vec<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SystemdScope {
+    All,
+    System,
+    User,
+}
+
+// https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.systemd1.html#Methods
+// name, description, load state, active state, sub state, followed unit, object path,
+// job id, job type, job object path
+type SystemdUnit = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    OwnedObjectPath,
+    u32,
+    String,
+    OwnedObjectPath,
+);
+
+#[derive(Debug)]
+pub struct SystemdStatus {
+    failed_units: Vec<String>,
+}
+
+impl CommandStatus<SystemdArgs> for SystemdStatus {
+    fn get(command: &SystemdArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let mut failed_units = Vec::new();
+
+        if matches!(command.scope, SystemdScope::All | SystemdScope::System) {
+            let connection = Connection::system()
+                .map_err(|e| I3DisplayError::from(format!("can't connect to system bus: {e}")))?;
+            failed_units.extend(Self::get_failed_units(&connection)?);
+        }
+        if matches!(command.scope, SystemdScope::All | SystemdScope::User) {
+            match Connection::session() {
+                Ok(connection) => failed_units.extend(Self::get_failed_units(&connection)?),
+                // without a session bus, only system units can be checked
+                Err(_) if matches!(command.scope, SystemdScope::All) => {}
+                Err(e) => {
+                    return Err(I3DisplayError::from(format!(
+                        "can't connect to session bus: {e}"
+                    )))
+                }
+            }
+        }
+
+        let systemd_status = SystemdStatus::new(failed_units, &command.ignore);
+        let (long_line, short_line) = systemd_status.i3blocks_print(command.list);
+        let color = match systemd_status.failed_units.is_empty() {
+            true => I3StatusRustColorState::I3StatusRustStateGood,
+            false => I3StatusRustColorState::I3StatusRustStateCritical,
+        };
+        Ok(Some(I3Display::new(
+            None,
+            long_line,
+            short_line,
+            Some(color),
+        )))
+    }
+}
+
+impl SystemdStatus {
+    fn new(mut failed_units: Vec<String>, ignore: &[String]) -> Self {
+        failed_units.retain(|unit| !ignore.contains(unit));
+        failed_units.sort();
+        failed_units.dedup();
+        Self { failed_units }
+    }
+
+    fn get_failed_units(connection: &Connection) -> Result<Vec<String>, I3DisplayError> {
+        let proxy = zbus::blocking::Proxy::new(
+            connection,
+            SYSTEMD_DESTINATION,
+            SYSTEMD_PATH,
+            SYSTEMD_MANAGER_INTERFACE,
+        )
+        .map_err(|e| I3DisplayError::from(format!("can't reach systemd manager: {e}")))?;
+
+        let units: Vec<SystemdUnit> = proxy
+            .call("ListUnitsFiltered", &(vec!["failed"],))
+            .map_err(|e| I3DisplayError::from(format!("can't list failed units: {e}")))?;
+
+        Ok(units.into_iter().map(|unit| unit.0).collect())
+    }
+
+    fn i3blocks_print(&self, list: bool) -> (String, String) {
+        let count = self.failed_units.len().to_string();
+        match list && !self.failed_units.is_empty() {
+            true => (format!("{count} {}", self.failed_units.join(" ")), count),
+            false => (count.clone(), count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::{blocking::connection, zvariant::OwnedObjectPath};
+
+    use super::{SystemdStatus, SystemdUnit, SYSTEMD_DESTINATION, SYSTEMD_PATH};
+    use crate::cmds::utils::spawn_session_bus;
+
+    struct FakeSystemdManager {
+        units: Vec<(String, String)>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.systemd1.Manager")]
+    impl FakeSystemdManager {
+        fn list_units_filtered(&self, states: Vec<String>) -> Vec<SystemdUnit> {
+            let root = OwnedObjectPath::try_from("/").unwrap();
+            self.units
+                .iter()
+                .filter(|(_, state)| states.contains(state))
+                .map(|(name, state)| {
+                    (
+                        name.clone(),
+                        String::new(),
+                        "loaded".to_string(),
+                        state.clone(),
+                        state.clone(),
+                        String::new(),
+                        root.clone(),
+                        0,
+                        String::new(),
+                        root.clone(),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_systemd_status() {
+        let failed_units = vec![
+            "flaky.timer".to_string(),
+            "backup.service".to_string(),
+            "backup.service".to_string(),
+        ];
+        let status = SystemdStatus::new(failed_units, &["flaky.timer".to_string()]);
+        assert_eq!(
+            status.i3blocks_print(true),
+            ("1 backup.service".to_string(), "1".to_string())
+        );
+        assert_eq!(
+            status.i3blocks_print(false),
+            ("1".to_string(), "1".to_string())
+        );
+        assert_eq!(
+            SystemdStatus::new(Vec::new(), &[]).i3blocks_print(true),
+            ("0".to_string(), "0".to_string())
+        );
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn test_systemd_failed_units() {
+        let (mut dbus_daemon, address) = spawn_session_bus().expect("dbus-daemon not found");
+
+        let manager = FakeSystemdManager {
+            units: vec![
+                ("backup.service".to_string(), "failed".to_string()),
+                ("flaky.timer".to_string(), "failed".to_string()),
+                ("sshd.service".to_string(), "active".to_string()),
+            ],
+        };
+        let _service = connection::Builder::address(address.as_str())
+            .unwrap()
+            .name(SYSTEMD_DESTINATION)
+            .unwrap()
+            .serve_at(SYSTEMD_PATH, manager)
+            .unwrap()
+            .build()
+            .unwrap();
+        let client = connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let failed_units = SystemdStatus::get_failed_units(&client);
+        dbus_daemon.kill().unwrap();
+        assert_eq!(failed_units.unwrap(), vec!["backup.service", "flaky.timer"]);
+    }
+}
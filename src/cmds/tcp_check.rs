@@ -1,30 +1,104 @@
-use std::net::TcpStream;
+use std::time::Duration;
 
 use clap::Args;
 
 use crate::{CommandStatus, I3Display, I3DisplayError};
 
+use super::utils::{
+    ascending_threshold_color, check_concurrently, connect_timeout_any, parse_key_value,
+    targets_status_display,
+};
+
 #[derive(Args)]
 pub struct TcpCheckArgs {
-    #[arg(short = 'o', long)]
-    pub host: String,
-    #[arg(short, long)]
-    pub port: u16,
+    #[arg(
+        short = 'o',
+        long,
+        required_unless_present = "target",
+        requires = "port"
+    )]
+    pub host: Option<String>,
+    #[arg(short, long, requires = "host")]
+    pub port: Option<u16>,
+    #[arg(long, value_parser = parse_tcp_target, conflicts_with = "host")]
+    pub target: Vec<TcpTarget>,
     #[arg(short, long, default_value = "up")]
     pub availability_text: Option<String>,
     #[arg(short, long)]
     pub unavailability_text: Option<String>,
+    #[arg(short, long, default_value_t = 1000)]
+    pub timeout_ms: u64,
+    // targets are only shown up or down
+    #[arg(short, long, default_value_t = false, conflicts_with = "target")]
+    pub latency: bool,
+    #[arg(short, long, default_value_t = 100)]
+    pub warning_ms: u64,
+    #[arg(short, long, default_value_t = 300)]
+    pub critical_ms: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TcpTarget {
+    label: String,
+    host: String,
+    port: u16,
+}
+
+/// Parse a `label=host:port` target, IPv6 hosts are written in brackets
+fn parse_tcp_target(value: &str) -> Result<TcpTarget, String> {
+    let (label, address) = parse_key_value(value)?;
+    let (host, port) = address
+        .rsplit_once(':')
+        .ok_or(format!("expected host:port, got `{address}`"))?;
+    let port = port
+        .parse::<u16>()
+        .map_err(|e| format!("invalid port `{port}`: {e}"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok(TcpTarget {
+        label,
+        host: host.to_string(),
+        port,
+    })
 }
 
 pub struct TcpCheck {
     available: bool,
+    latency_ms: Option<f64>,
 }
 
 impl CommandStatus<TcpCheckArgs> for TcpCheck {
     fn get(command: &TcpCheckArgs) -> Result<Option<I3Display>, I3DisplayError> {
-        let tcp_check = TcpCheck::check(command.host.clone(), command.port);
+        let timeout = Duration::from_millis(command.timeout_ms);
+        if !command.target.is_empty() {
+            let results = check_concurrently(&command.target, |target| {
+                (
+                    target.label.clone(),
+                    TcpCheck::check(&target.host, target.port, timeout).available,
+                )
+            });
+            return Ok(Some(targets_status_display(&results)));
+        }
+
+        let (Some(host), Some(port)) = (&command.host, command.port) else {
+            return Err(I3DisplayError::from(
+                "a host and a port, or targets, are required".to_string(),
+            ));
+        };
+        let tcp_check = TcpCheck::check(host, port, timeout);
         match tcp_check.available {
             true => {
+                if let (true, Some(latency_ms)) = (command.latency, tcp_check.latency_ms) {
+                    let x = match &command.availability_text {
+                        Some(text) => format!("{text} {latency_ms:.0}ms"),
+                        None => format!("{latency_ms:.0}ms"),
+                    };
+                    let color = ascending_threshold_color(
+                        latency_ms,
+                        command.warning_ms as f64,
+                        command.critical_ms as f64,
+                    );
+                    return Ok(Some(I3Display::new(None, x.clone(), x, Some(color))));
+                }
                 if command.availability_text.is_some() {
                     let x = command.availability_text.clone().unwrap();
                     return Ok(Some(I3Display::new(None, x.clone(), x, None)));
@@ -42,23 +116,86 @@ impl CommandStatus<TcpCheckArgs> for TcpCheck {
 }
 
 impl TcpCheck {
-    fn check(host: String, port: u16) -> Self {
-        let mut tcp_check = TcpCheck { available: false };
-        let addr = format!("{}:{}", host, port);
-        if TcpStream::connect(addr.as_str()).is_ok() {
-            tcp_check.available = true;
-        };
-        tcp_check
+    /// Unavailable when no resolved address accepts the connection
+    fn check(host: &str, port: u16, timeout: Duration) -> Self {
+        match connect_timeout_any(host, port, timeout) {
+            Ok((_, elapsed)) => TcpCheck {
+                available: true,
+                latency_ms: Some(elapsed.as_secs_f64() * 1000.0),
+            },
+            Err(_) => TcpCheck {
+                available: false,
+                latency_ms: None,
+            },
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::TcpCheck;
+    use std::{
+        net::TcpListener,
+        time::{Duration, Instant},
+    };
+
+    use clap::Parser;
+
+    use super::{parse_tcp_target, TcpCheck, TcpCheckArgs, TcpTarget};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: TcpCheckArgs,
+    }
 
     #[test]
     fn test_tcp_check_connectivity() {
-        let x = TcpCheck::check("google.com".to_string(), 80);
-        assert_eq!(x.available, true);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let x = TcpCheck::check("localhost", port, Duration::from_secs(1));
+        assert!(x.available);
+        assert!(x.latency_ms.is_some());
+
+        drop(listener);
+        let x = TcpCheck::check("127.0.0.1", port, Duration::from_secs(1));
+        assert!(!x.available);
+        assert!(x.latency_ms.is_none());
+    }
+
+    #[test]
+    fn test_tcp_check_timeout() {
+        let start = Instant::now();
+        // TEST-NET-1, reserved for documentation so never routed
+        let x = TcpCheck::check("192.0.2.1", 80, Duration::from_millis(200));
+        assert!(!x.available);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let x = TcpCheck::check("invalid.invalid", 80, Duration::from_millis(200));
+        assert!(!x.available);
+    }
+
+    #[test]
+    fn test_parse_tcp_target() {
+        assert_eq!(
+            parse_tcp_target("nas=nas.lan:445"),
+            Ok(TcpTarget {
+                label: "nas".to_string(),
+                host: "nas.lan".to_string(),
+                port: 445,
+            })
+        );
+        assert_eq!(
+            parse_tcp_target("dns=[::1]:53").map(|x| x.host),
+            Ok("::1".to_string())
+        );
+        assert!(parse_tcp_target("nas=nas.lan").is_err());
+        assert!(parse_tcp_target("nas=nas.lan:smb").is_err());
+        assert!(parse_tcp_target("nas.lan:445").is_err());
+
+        assert!(Cli::try_parse_from(["tcp-check", "--target", "nas=nas.lan:445"]).is_ok());
+        assert!(
+            Cli::try_parse_from(["tcp-check", "--target", "nas=nas.lan:445", "--latency"]).is_err()
+        );
     }
 }
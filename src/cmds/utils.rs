@@ -1,4 +1,17 @@
-use crate::I3StatusRustColorState;
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+use reqwest::blocking::Client;
+
+use crate::{BytesStandard, BytesUnit, I3Display, I3DisplayError, I3StatusRustColorState};
+
+const IEC_UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+const SI_UNITS: [&str; 5] = ["kB", "MB", "GB", "TB", "PB"];
+const TARGET_UP: &str = "󰄬";
+const TARGET_DOWN: &str = "󰅖";
 
 pub fn define_threshold_color(
     warning: u8,
@@ -17,6 +30,38 @@ pub fn define_threshold_color(
     }
 }
 
+/// Color of a value getting worse as it grows: good below `warning`, warning below
+/// `critical`, critical above
+pub fn ascending_threshold_color(
+    value: f64,
+    warning: f64,
+    critical: f64,
+) -> I3StatusRustColorState {
+    if value >= critical {
+        I3StatusRustColorState::I3StatusRustStateCritical
+    } else if value >= warning {
+        I3StatusRustColorState::I3StatusRustStateWarning
+    } else {
+        I3StatusRustColorState::I3StatusRustStateGood
+    }
+}
+
+/// Color of a value getting worse as it shrinks: good from `warning`, warning from
+/// `critical`, critical below
+pub fn descending_threshold_color(
+    value: f64,
+    warning: f64,
+    critical: f64,
+) -> I3StatusRustColorState {
+    if value < critical {
+        I3StatusRustColorState::I3StatusRustStateCritical
+    } else if value < warning {
+        I3StatusRustColorState::I3StatusRustStateWarning
+    } else {
+        I3StatusRustColorState::I3StatusRustStateGood
+    }
+}
+
 pub fn set_text_threshold_color(
     warning: f64,
     danger: f64,
@@ -35,3 +80,315 @@ pub fn set_text_threshold_color(
         final_value
     }
 }
+
+/// Convert bytes to the requested unit, or the most readable one with `Auto`
+pub fn scale_bytes(bytes: f64, unit: BytesUnit, standard: BytesStandard) -> (f64, &'static str) {
+    let (base, units) = match standard {
+        BytesStandard::Iec => (1024_f64, IEC_UNITS),
+        BytesStandard::Si => (1000_f64, SI_UNITS),
+    };
+    let exponent = match unit {
+        BytesUnit::Auto => (1..units.len())
+            .take_while(|x| bytes.abs() >= base.powi(*x as i32 + 1))
+            .last()
+            .unwrap_or(0),
+        BytesUnit::Kb => 0,
+        BytesUnit::Mb => 1,
+        BytesUnit::Gb => 2,
+        BytesUnit::Tb => 3,
+        BytesUnit::Pb => 4,
+    };
+    (bytes / base.powi(exponent as i32 + 1), units[exponent])
+}
+
+pub fn format_bytes(
+    bytes: f64,
+    unit: BytesUnit,
+    standard: BytesStandard,
+    precision: usize,
+) -> String {
+    let (value, unit) = scale_bytes(bytes, unit, standard);
+    format!("{value:.precision$}{unit}")
+}
+
+/// Parse a duration like `90`, `30s`, `15m`, `24h` or `7d` into seconds
+pub fn parse_duration(value: &str) -> Result<u64, String> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1),
+        Some((i, 'm')) => (&value[..i], 60),
+        Some((i, 'h')) => (&value[..i], 60 * 60),
+        Some((i, 'd')) => (&value[..i], 24 * 60 * 60),
+        _ => (value, 1),
+    };
+    let seconds = number
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration `{value}`, expected e.g. 30s, 15m, 24h or 7d"))?;
+    seconds
+        .checked_mul(multiplier)
+        .ok_or(format!("duration `{value}` is too long"))
+}
+
+/// Try every resolved address until one accepts the connection within the timeout,
+/// returns the stream with the time this last connection took
+pub fn connect_timeout_any(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<(TcpStream, Duration), I3DisplayError> {
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| I3DisplayError::from(format!("can't resolve {host}: {e}")))?;
+    let mut error = format!("can't resolve {host}");
+    for addr in addrs {
+        let start = Instant::now();
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(x) => return Ok((x, start.elapsed())),
+            Err(e) => error = format!("can't connect to {addr}: {e}"),
+        }
+    }
+    Err(I3DisplayError::from(error))
+}
+
+/// Blocking HTTP client shared by the blocks querying web services
+pub fn http_client(timeout: Duration, insecure: bool) -> Result<Client, I3DisplayError> {
+    Client::builder()
+        .timeout(timeout)
+        .danger_accept_invalid_certs(insecure)
+        .build()
+        .map_err(|e| I3DisplayError::from(format!("can't build http client: {e}")))
+}
+
+/// Run `check` on every target concurrently, results are in the targets order
+pub fn check_concurrently<T: Sync, R: Send>(
+    targets: &[T],
+    check: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let check = &check;
+    thread::scope(|s| {
+        targets
+            .iter()
+            .map(|x| s.spawn(move || check(x)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|x| x.join().expect("target check panicked"))
+            .collect()
+    })
+}
+
+/// Render the status of labeled targets: good when all are up, warning when
+/// some are down, critical when all are down
+pub fn targets_status_display(targets: &[(String, bool)]) -> I3Display {
+    let glyph = |available: bool| match available {
+        true => TARGET_UP,
+        false => TARGET_DOWN,
+    };
+    let long_line = targets
+        .iter()
+        .map(|(label, available)| format!("{label} {}", glyph(*available)))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let short_line = targets
+        .iter()
+        .map(|(_, available)| glyph(*available))
+        .collect::<String>();
+
+    let up = targets.iter().filter(|(_, available)| *available).count();
+    let color = if up == targets.len() {
+        I3StatusRustColorState::I3StatusRustStateGood
+    } else if up == 0 {
+        I3StatusRustColorState::I3StatusRustStateCritical
+    } else {
+        I3StatusRustColorState::I3StatusRustStateWarning
+    };
+    I3Display::new(None, long_line, short_line, Some(color))
+}
+
+/// Parse a `key=value` pair
+pub fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid value `{value}`, expected key=value")),
+    }
+}
+
+/// Start a private session bus with its address, `None` if dbus-daemon is not installed
+#[cfg(test)]
+pub fn spawn_session_bus() -> Option<(std::process::Child, String)> {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+
+    let mut dbus_daemon = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut address = String::new();
+    BufReader::new(dbus_daemon.stdout.take()?)
+        .read_line(&mut address)
+        .ok()?;
+    Some((dbus_daemon, address.trim().to_string()))
+}
+
+/// Writes fixture files under `root`, creating their directories
+#[cfg(test)]
+pub fn write_files(root: &std::path::Path, files: &[(&str, &str)]) {
+    for (file, content) in files {
+        std::fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+        std::fs::write(root.join(file), content).unwrap();
+    }
+}
+
+/// Local HTTP server answering `count` requests with the status and body returned by
+/// `respond` for the request head. The request heads are returned on join
+#[cfg(test)]
+pub fn serve_http(
+    count: usize,
+    respond: impl Fn(&str) -> (&'static str, String) + Send + 'static,
+) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for _ in 0..count {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                    break;
+                }
+                request.push_str(&line);
+            }
+            let (status, body) = respond(&request);
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            requests.push(request);
+        }
+        requests
+    });
+    (url, server)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use super::{
+        ascending_threshold_color, check_concurrently, connect_timeout_any,
+        descending_threshold_color, format_bytes, parse_duration, parse_key_value,
+        targets_status_display,
+    };
+    use crate::{BytesStandard, BytesUnit, I3StatusRustColorState};
+
+    #[test]
+    fn test_format_bytes() {
+        let gib = 1024.0 * 1024.0 * 1024.0;
+        let iec = |bytes, unit| format_bytes(bytes, unit, BytesStandard::Iec, 1);
+        assert_eq!(iec(1.5 * gib, BytesUnit::Gb), "1.5GiB");
+        assert_eq!(iec(1.5 * gib, BytesUnit::Mb), "1536.0MiB");
+        assert_eq!(iec(1.5 * gib, BytesUnit::Auto), "1.5GiB");
+        assert_eq!(iec(3.0 * 1024.0 * 1024.0 * gib, BytesUnit::Auto), "3.0PiB");
+        assert_eq!(iec(0.0, BytesUnit::Auto), "0.0KiB");
+        assert_eq!(iec(0.2 * gib, BytesUnit::Gb), "0.2GiB");
+
+        let si = |bytes, unit, precision| format_bytes(bytes, unit, BytesStandard::Si, precision);
+        assert_eq!(si(1.5 * gib, BytesUnit::Auto, 2), "1.61GB");
+        assert_eq!(si(2_500_000_000_000.0, BytesUnit::Auto, 0), "2TB");
+        assert_eq!(si(999.0, BytesUnit::Auto, 1), "1.0kB");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(90));
+        assert_eq!(parse_duration("30s"), Ok(30));
+        assert_eq!(parse_duration("15m"), Ok(900));
+        assert_eq!(parse_duration("24h"), Ok(86400));
+        assert_eq!(parse_duration("7d"), Ok(604800));
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("-1h").is_err());
+        assert!(parse_duration("99999999999999999d").is_err());
+    }
+
+    #[test]
+    fn test_check_concurrently() {
+        let start = std::time::Instant::now();
+        let results = check_concurrently(&[3, 1, 2], |x| {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            x * 2
+        });
+        assert_eq!(results, vec![6, 2, 4]);
+        assert!(start.elapsed() < std::time::Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_targets_status_display() {
+        let targets = |states: &[bool]| {
+            states
+                .iter()
+                .enumerate()
+                .map(|(i, x)| (format!("t{i}"), *x))
+                .collect::<Vec<(String, bool)>>()
+        };
+
+        let display = targets_status_display(&targets(&[true, false]));
+        assert_eq!(display.long_line, "t0 󰄬 t1 󰅖");
+        assert_eq!(display.short_line, "󰄬󰅖");
+        assert!(display.color == Some(I3StatusRustColorState::I3StatusRustStateWarning));
+
+        let display = targets_status_display(&targets(&[true, true]));
+        assert!(display.color == Some(I3StatusRustColorState::I3StatusRustStateGood));
+        let display = targets_status_display(&targets(&[false, false]));
+        assert!(display.color == Some(I3StatusRustColorState::I3StatusRustStateCritical));
+    }
+
+    #[test]
+    fn test_parse_key_value() {
+        assert_eq!(
+            parse_key_value("quiet=󰖔"),
+            Ok(("quiet".to_string(), "󰖔".to_string()))
+        );
+        assert_eq!(
+            parse_key_value("nas=nas.lan:445"),
+            Ok(("nas".to_string(), "nas.lan:445".to_string()))
+        );
+        assert!(parse_key_value("quiet").is_err());
+        assert!(parse_key_value("=x").is_err());
+    }
+
+    #[test]
+    fn test_connect_timeout_any() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let timeout = Duration::from_secs(1);
+        let (_, elapsed) = connect_timeout_any("localhost", port, timeout).unwrap();
+        assert!(elapsed < timeout);
+
+        drop(listener);
+        let error = connect_timeout_any("127.0.0.1", port, timeout).unwrap_err();
+        assert!(error.message.starts_with("can't connect to 127.0.0.1:"));
+        assert!(connect_timeout_any("nonexistent.invalid", port, timeout).is_err());
+    }
+
+    #[test]
+    fn test_threshold_color_ladders() {
+        let color = |x| ascending_threshold_color(x, 100.0, 300.0);
+        assert!(color(20.0) == I3StatusRustColorState::I3StatusRustStateGood);
+        assert!(color(100.0) == I3StatusRustColorState::I3StatusRustStateWarning);
+        assert!(color(299.9) == I3StatusRustColorState::I3StatusRustStateWarning);
+        assert!(color(300.0) == I3StatusRustColorState::I3StatusRustStateCritical);
+
+        let color = |x| descending_threshold_color(x, 60.0, 30.0);
+        assert!(color(60.0) == I3StatusRustColorState::I3StatusRustStateGood);
+        assert!(color(59.9) == I3StatusRustColorState::I3StatusRustStateWarning);
+        assert!(color(30.0) == I3StatusRustColorState::I3StatusRustStateWarning);
+        assert!(color(29.9) == I3StatusRustColorState::I3StatusRustStateCritical);
+    }
+}
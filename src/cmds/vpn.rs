@@ -0,0 +1,396 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use clap::{Args, ValueEnum};
+use compound_duration::format_dhms;
+
+use crate::{
+    BytesStandard, BytesUnit, CommandStatus, I3Display, I3DisplayError, I3StatusRustColorState,
+};
+
+use super::{
+    state::unix_timestamp,
+    utils::{format_bytes, parse_duration},
+};
+
+const SYSFS_NET_PATH: &str = "/sys/class/net";
+const WG_BIN: &str = "wg";
+// ARPHRD_NONE, used by WireGuard and tun interfaces
+const ARPHRD_NONE: &str = "65534";
+const IFF_UP: u32 = 0x1;
+
+#[derive(Args)]
+pub struct VpnArgs {
+    // all detected tunnels by default
+    #[arg(short, long, value_delimiter = ',')]
+    pub interface: Vec<String>,
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        default_value = "handshake,transfer"
+    )]
+    pub display: Vec<VpnDisplay>,
+    // WireGuard renews sessions every 2 minutes when there is traffic
+    #[arg(short, long, default_value = "3m", value_parser = parse_duration)]
+    pub stale_handshake: u64,
+    #[arg(long, default_value = "iec")]
+    pub standard: BytesStandard,
+    #[arg(long, default_value_t = 1)]
+    pub precision: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum VpnDisplay {
+    Handshake,
+    Transfer,
+}
+
+#[derive(Debug, PartialEq)]
+enum TunnelKind {
+    WireGuard,
+    Tun,
+    Tap,
+}
+
+#[derive(Debug)]
+struct Tunnel {
+    name: String,
+    kind: TunnelKind,
+    up: bool,
+    // unix timestamp of the most recent peer handshake, 0 when there was none,
+    // `None` when unknown (not WireGuard, or `wg` can't be run)
+    latest_handshake: Option<u64>,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct VpnStatus {
+    tunnels: Vec<Tunnel>,
+}
+
+impl CommandStatus<VpnArgs> for VpnStatus {
+    fn get(command: &VpnArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let vpn_status = Self::new(Path::new(SYSFS_NET_PATH), &command.interface, || {
+            Self::wireguard_dump(WG_BIN)
+        });
+        if vpn_status.tunnels.is_empty() {
+            return Ok(None);
+        }
+
+        let now = unix_timestamp();
+        let (long_line, short_line) = vpn_status.i3blocks_print(command, now);
+        let color = vpn_status
+            .tunnels
+            .iter()
+            .map(|x| x.color(now, command.stale_handshake))
+            .max()
+            .unwrap_or(I3StatusRustColorState::I3StatusRustStateGood);
+        Ok(Some(I3Display::new(
+            None,
+            long_line,
+            short_line,
+            Some(color),
+        )))
+    }
+}
+
+impl VpnStatus {
+    /// Requested interfaces missing from sysfs are reported as down tunnels. The
+    /// WireGuard dump is only taken when there is a WireGuard tunnel
+    fn new(
+        sysfs_net: &Path,
+        interfaces: &[String],
+        wg_dump: impl FnOnce() -> Option<String>,
+    ) -> Self {
+        let mut tunnels = Self::get_tunnels(sysfs_net);
+        if !interfaces.is_empty() {
+            tunnels.retain(|x| interfaces.contains(&x.name));
+            for interface in interfaces {
+                if !tunnels.iter().any(|x| &x.name == interface) {
+                    tunnels.push(Tunnel {
+                        name: interface.clone(),
+                        kind: TunnelKind::Tun,
+                        up: false,
+                        latest_handshake: None,
+                        rx_bytes: 0,
+                        tx_bytes: 0,
+                    });
+                }
+            }
+        }
+
+        if tunnels.iter().any(|x| x.kind == TunnelKind::WireGuard) {
+            let handshakes = wg_dump()
+                .map(|x| Self::parse_wireguard_handshakes(&x))
+                .unwrap_or_default();
+            for tunnel in tunnels.iter_mut() {
+                tunnel.latest_handshake = handshakes.get(&tunnel.name).copied();
+            }
+        }
+        Self { tunnels }
+    }
+
+    fn get_tunnels(sysfs_net: &Path) -> Vec<Tunnel> {
+        let read = |path: PathBuf| fs::read_to_string(path).unwrap_or_default();
+        let parse = |path: PathBuf| read(path).trim().parse::<u64>().unwrap_or(0);
+
+        let interfaces = match fs::read_dir(sysfs_net) {
+            Ok(x) => x,
+            Err(_) => return Vec::new(),
+        };
+        let mut tunnels = Vec::new();
+        for interface in interfaces.filter_map(|x| x.ok()).map(|x| x.path()) {
+            let kind = if read(interface.join("uevent"))
+                .lines()
+                .any(|x| x == "DEVTYPE=wireguard")
+            {
+                TunnelKind::WireGuard
+            } else if !interface.join("tun_flags").exists() {
+                continue;
+            } else if read(interface.join("type")).trim() == ARPHRD_NONE {
+                TunnelKind::Tun
+            } else {
+                TunnelKind::Tap
+            };
+            let flags = u32::from_str_radix(
+                read(interface.join("flags"))
+                    .trim()
+                    .trim_start_matches("0x"),
+                16,
+            )
+            .unwrap_or(0);
+
+            tunnels.push(Tunnel {
+                name: interface
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                kind,
+                up: flags & IFF_UP != 0 && read(interface.join("operstate")).trim() != "down",
+                latest_handshake: None,
+                rx_bytes: parse(interface.join("statistics/rx_bytes")),
+                tx_bytes: parse(interface.join("statistics/tx_bytes")),
+            });
+        }
+        tunnels.sort_by(|a, b| a.name.cmp(&b.name));
+        tunnels
+    }
+
+    /// Output of `wg show all dump`, None when `wg` is missing or not allowed to read
+    /// the interfaces
+    fn wireguard_dump(wg_bin: &str) -> Option<String> {
+        match Command::new(wg_bin).args(["show", "all", "dump"]).output() {
+            Ok(x) if x.status.success() => Some(String::from_utf8_lossy(&x.stdout).to_string()),
+            _ => None,
+        }
+    }
+
+    /// Most recent handshake of each WireGuard interface
+    fn parse_wireguard_handshakes(dump: &str) -> HashMap<String, u64> {
+        let mut handshakes = HashMap::new();
+        for line in dump.lines() {
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            match fields.len() {
+                // interface, private key, public key, listen port, fwmark
+                5 => {
+                    handshakes.entry(fields[0].to_string()).or_insert(0);
+                }
+                // interface, public key, preshared key, endpoint, allowed ips,
+                // latest handshake, transfer rx, transfer tx, persistent keepalive
+                9 => {
+                    let handshake = fields[5].parse::<u64>().unwrap_or(0);
+                    let latest = handshakes.entry(fields[0].to_string()).or_insert(0);
+                    *latest = handshake.max(*latest);
+                }
+                _ => {}
+            }
+        }
+        handshakes
+    }
+
+    fn i3blocks_print(&self, command: &VpnArgs, now: u64) -> (String, String) {
+        let mut long_line = Vec::new();
+        let mut short_line = Vec::new();
+        for tunnel in &self.tunnels {
+            let mut fields = vec![tunnel.name.clone()];
+            if !tunnel.up {
+                fields.push("down".to_string());
+                long_line.push(fields.join(" "));
+                short_line.push(fields.join(" "));
+                continue;
+            }
+            if let (true, Some(handshake)) = (
+                command.display.contains(&VpnDisplay::Handshake),
+                tunnel.handshake_age(now),
+            ) {
+                fields.push(handshake);
+            }
+            short_line.push(fields.join(" "));
+            if command.display.contains(&VpnDisplay::Transfer) {
+                let format = |x: u64| {
+                    format_bytes(
+                        x as f64,
+                        BytesUnit::Auto,
+                        command.standard,
+                        command.precision,
+                    )
+                };
+                fields.push(format!("↓{}", format(tunnel.rx_bytes)));
+                fields.push(format!("↑{}", format(tunnel.tx_bytes)));
+            }
+            long_line.push(fields.join(" "));
+        }
+        (long_line.join(" "), short_line.join(" "))
+    }
+}
+
+impl Tunnel {
+    /// `?` for a WireGuard tunnel when `wg` can't be run
+    fn handshake_age(&self, now: u64) -> Option<String> {
+        match self.latest_handshake {
+            None if self.kind == TunnelKind::WireGuard => Some("?".to_string()),
+            None => None,
+            Some(0) => Some("never".to_string()),
+            Some(x) => Some(format_dhms(now.saturating_sub(x) as usize)),
+        }
+    }
+
+    fn color(&self, now: u64, stale_handshake: u64) -> I3StatusRustColorState {
+        if !self.up {
+            return I3StatusRustColorState::I3StatusRustStateWarning;
+        }
+        match self.latest_handshake {
+            Some(x) if x == 0 || now.saturating_sub(x) > stale_handshake => {
+                I3StatusRustColorState::I3StatusRustStateCritical
+            }
+            // without `wg`, a stale tunnel can't be told from a working one
+            None if self.kind == TunnelKind::WireGuard => {
+                I3StatusRustColorState::I3StatusRustStateWarning
+            }
+            _ => I3StatusRustColorState::I3StatusRustStateGood,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use clap::Parser;
+
+    use super::{TunnelKind, VpnArgs, VpnStatus};
+    use crate::{cmds::utils::write_files, I3StatusRustColorState};
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: VpnArgs,
+    }
+
+    #[test]
+    fn test_vpn_status() {
+        let root = std::env::temp_dir().join(format!("vpn-{}", std::process::id()));
+        let sysfs_net = root.join("net");
+        write_files(
+            &sysfs_net,
+            &[
+                ("eth0/type", "1\n"),
+                ("eth0/flags", "0x1003\n"),
+                ("wg0/type", "65534\n"),
+                (
+                    "wg0/uevent",
+                    "DEVTYPE=wireguard\nINTERFACE=wg0\nIFINDEX=5\n",
+                ),
+                ("wg0/flags", "0x91\n"),
+                ("wg0/operstate", "unknown\n"),
+                ("wg0/statistics/rx_bytes", "1572864\n"),
+                ("wg0/statistics/tx_bytes", "2048\n"),
+                ("wg1/type", "65534\n"),
+                ("wg1/uevent", "DEVTYPE=wireguard\nINTERFACE=wg1\n"),
+                ("wg1/flags", "0x91\n"),
+                ("wg1/operstate", "unknown\n"),
+                ("tun0/type", "65534\n"),
+                ("tun0/tun_flags", "0x1001\n"),
+                ("tun0/flags", "0x1090\n"),
+                ("tun0/operstate", "down\n"),
+                ("tap0/type", "1\n"),
+                ("tap0/tun_flags", "0x1002\n"),
+                ("tap0/flags", "0x1003\n"),
+                ("tap0/operstate", "up\n"),
+            ],
+        );
+        let wg_dump = || {
+            Some(
+                "wg0\tpriv\tpub\t51820\toff\n\
+wg0\tpeer1\t(none)\t1.2.3.4:51820\t10.0.0.0/24\t1000\t10\t20\toff\n\
+wg0\tpeer2\t(none)\t(none)\t10.0.1.0/24\t1100\t0\t0\toff\n\
+wg1\tpriv\tpub\t51821\toff\n"
+                    .to_string(),
+            )
+        };
+
+        let vpn_status = VpnStatus::new(&sysfs_net, &[], wg_dump);
+        let tunnels = vpn_status
+            .tunnels
+            .iter()
+            .map(|x| (x.name.as_str(), &x.kind, x.up, x.latest_handshake))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tunnels,
+            vec![
+                ("tap0", &TunnelKind::Tap, true, None),
+                ("tun0", &TunnelKind::Tun, false, None),
+                ("wg0", &TunnelKind::WireGuard, true, Some(1100)),
+                ("wg1", &TunnelKind::WireGuard, true, Some(0)),
+            ]
+        );
+        assert!(
+            vpn_status.tunnels[2].color(1200, 180) == I3StatusRustColorState::I3StatusRustStateGood
+        );
+        assert!(
+            vpn_status.tunnels[2].color(1300, 180)
+                == I3StatusRustColorState::I3StatusRustStateCritical
+        );
+        assert!(
+            vpn_status.tunnels[3].color(1200, 180)
+                == I3StatusRustColorState::I3StatusRustStateCritical
+        );
+        assert!(
+            vpn_status.tunnels[1].color(1200, 180)
+                == I3StatusRustColorState::I3StatusRustStateWarning
+        );
+
+        let cli = Cli::parse_from(["vpn", "-i", "wg0,wg9"]);
+        let vpn_status = VpnStatus::new(&sysfs_net, &cli.args.interface, wg_dump);
+        assert_eq!(
+            vpn_status.i3blocks_print(&cli.args, 1130),
+            (
+                "wg0 30s ↓1.5MiB ↑2.0KiB wg9 down".to_string(),
+                "wg0 30s wg9 down".to_string()
+            )
+        );
+
+        // wg can't be run, tunnels are still listed
+        let vpn_status = VpnStatus::new(&sysfs_net, &cli.args.interface, || {
+            VpnStatus::wireguard_dump("/nonexistent/wg")
+        });
+        assert_eq!(vpn_status.tunnels[0].latest_handshake, None);
+        assert_eq!(
+            vpn_status.i3blocks_print(&cli.args, 1130).1,
+            "wg0 ? wg9 down"
+        );
+        assert!(
+            vpn_status.tunnels[0].color(1130, 180)
+                == I3StatusRustColorState::I3StatusRustStateWarning
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+use std::{fs, path::Path, process::Command};
+
+use clap::{Args, ValueEnum};
+
+use crate::{CommandStatus, I3Display, I3DisplayError};
+
+use super::utils::descending_threshold_color;
+
+const PROC_WIRELESS_PATH: &str = "/proc/net/wireless";
+const IW_BIN: &str = "iw";
+
+#[derive(Args)]
+pub struct WifiArgs {
+    // first wireless interface of /proc/net/wireless by default
+    #[arg(short, long)]
+    pub interface: Option<String>,
+    #[arg(short, long, value_delimiter = ',', default_value = "ssid,quality")]
+    pub display: Vec<WifiDisplay>,
+    #[arg(short, long, default_value_t = 30, value_parser = clap::value_parser!(u8).range(..100))]
+    pub critical_quality: u8,
+    #[arg(short, long, default_value_t = 50, value_parser = clap::value_parser!(u8).range(..100))]
+    pub warning_quality: u8,
+    #[arg(short, long)]
+    pub unavailability_text: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum WifiDisplay {
+    Ssid,
+    Signal,
+    Quality,
+    Bitrate,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct WifiStatus {
+    ssid: Option<String>,
+    signal_dbm: Option<f64>,
+    bitrate_mbps: Option<f64>,
+}
+
+impl CommandStatus<WifiArgs> for WifiStatus {
+    fn get(command: &WifiArgs) -> Result<Option<I3Display>, I3DisplayError> {
+        let wifi_status = Self::new(
+            Path::new(PROC_WIRELESS_PATH),
+            command.interface.as_deref(),
+            |x| Self::iw_link(IW_BIN, x),
+        )?;
+
+        let Some(wifi_status) = wifi_status else {
+            return Ok(command
+                .unavailability_text
+                .clone()
+                .map(|x| I3Display::new(None, x.clone(), x, None)));
+        };
+        let (long_line, short_line) = wifi_status.i3blocks_print(&command.display);
+        // no color when the signal is unknown
+        let color = wifi_status.quality_percent().map(|x| {
+            descending_threshold_color(
+                x as f64,
+                command.warning_quality as f64,
+                command.critical_quality as f64,
+            )
+        });
+        Ok(Some(I3Display::new(None, long_line, short_line, color)))
+    }
+}
+
+impl WifiStatus {
+    /// Link details come from `iw`, `/proc/net/wireless` only gives the signal when it
+    /// isn't installed. `None` when disconnected or without wireless interface
+    fn new(
+        proc_wireless: &Path,
+        interface: Option<&str>,
+        iw_link: impl FnOnce(&str) -> Result<Option<String>, I3DisplayError>,
+    ) -> Result<Option<Self>, I3DisplayError> {
+        let interfaces = Self::get_wireless_interfaces(proc_wireless);
+        let (interface, level) = match interface {
+            Some(x) => (
+                x.to_string(),
+                interfaces
+                    .iter()
+                    .find(|(name, _)| name == x)
+                    .and_then(|(_, level)| *level),
+            ),
+            // radio off or interface down
+            None => match interfaces.into_iter().next() {
+                Some(x) => x,
+                None => return Ok(None),
+            },
+        };
+
+        match iw_link(&interface)? {
+            Some(x) => Ok(Self::parse_iw_link(&x)),
+            None => Ok(level.map(|x| WifiStatus {
+                signal_dbm: Some(x),
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Output of `iw dev <interface> link`, `None` when `iw` isn't installed
+    fn iw_link(iw_bin: &str, interface: &str) -> Result<Option<String>, I3DisplayError> {
+        match Command::new(iw_bin)
+            .args(["dev", interface, "link"])
+            .output()
+        {
+            Ok(x) if x.status.success() => Ok(Some(String::from_utf8_lossy(&x.stdout).to_string())),
+            Ok(x) => Err(I3DisplayError::from(format!(
+                "{iw_bin} failed: {}",
+                String::from_utf8_lossy(&x.stderr).trim()
+            ))),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Interfaces with their signal level in dBm, `None` when not associated
+    fn get_wireless_interfaces(proc_wireless: &Path) -> Vec<(String, Option<f64>)> {
+        let content = fs::read_to_string(proc_wireless).unwrap_or_default();
+        // 2 header lines, then `wlan0: 0000   54.  -56.  -256 ...`
+        content
+            .lines()
+            .skip(2)
+            .filter_map(|line| {
+                let (name, stats) = line.split_once(':')?;
+                let mut fields = stats.split_whitespace().skip(1);
+                let link = fields.next()?.trim_end_matches('.').parse::<f64>().ok()?;
+                let level = fields.next()?.trim_end_matches('.').parse::<f64>().ok()?;
+                Some((
+                    name.trim().to_string(),
+                    match link > 0.0 {
+                        true => Some(level),
+                        false => None,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Parse `iw dev <interface> link`, `None` when not connected
+    fn parse_iw_link(output: &str) -> Option<Self> {
+        if !output.starts_with("Connected to") {
+            return None;
+        }
+        let mut wifi_status = WifiStatus::default();
+        for line in output.lines().map(str::trim) {
+            if let Some(x) = line.strip_prefix("SSID: ") {
+                wifi_status.ssid = Some(x.to_string());
+            } else if let Some(x) = line.strip_prefix("signal: ") {
+                wifi_status.signal_dbm = x.split_whitespace().next().and_then(|x| x.parse().ok());
+            } else if let Some(x) = line.strip_prefix("tx bitrate: ") {
+                wifi_status.bitrate_mbps = x.split_whitespace().next().and_then(|x| x.parse().ok());
+            }
+        }
+        Some(wifi_status)
+    }
+
+    /// Linear from -100dBm (0%) to -50dBm (100%), like NetworkManager
+    fn quality_percent(&self) -> Option<u8> {
+        self.signal_dbm
+            .map(|x| (2.0 * (x + 100.0)).clamp(0.0, 100.0) as u8)
+    }
+
+    fn i3blocks_print(&self, display: &[WifiDisplay]) -> (String, String) {
+        let fields = display
+            .iter()
+            .filter_map(|x| {
+                let field = match x {
+                    WifiDisplay::Ssid => self.ssid.clone()?,
+                    WifiDisplay::Signal => format!("{:.0}dBm", self.signal_dbm?),
+                    WifiDisplay::Quality => format!("{}%", self.quality_percent()?),
+                    WifiDisplay::Bitrate => format!("{:.0}Mbit/s", self.bitrate_mbps?),
+                };
+                Some((x, field))
+            })
+            .collect::<Vec<_>>();
+        let long_line = fields
+            .iter()
+            .map(|(_, x)| x.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        // the SSID is the longest field, left out of the short line
+        let short_line = fields
+            .iter()
+            .filter(|(x, _)| **x != WifiDisplay::Ssid)
+            .map(|(_, x)| x.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        (long_line, short_line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{WifiDisplay, WifiStatus};
+
+    const IW_LINK: &str = "Connected to aa:bb:cc:dd:ee:ff (on wlan0)
+\tSSID: Home Network
+\tfreq: 5180
+\tRX: 123456 bytes (789 packets)
+\tTX: 65432 bytes (321 packets)
+\tsignal: -62 dBm
+\trx bitrate: 866.7 MBit/s VHT-MCS 9 80MHz short GI VHT-NSS 2
+\ttx bitrate: 780.0 MBit/s VHT-MCS 8 80MHz short GI VHT-NSS 2
+
+\tbss flags:\tshort-slot-time
+\tdtim period:\t1
+\tbeacon int:\t100
+";
+
+    const PROC_WIRELESS: &str =
+        "Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+ wlan0: 0000   48.  -62.  -256        0      0      0      0     12        0
+ wlan1: 0000    0.    0.  -256        0      0      0      0      0        0
+";
+
+    #[test]
+    fn test_wifi_parse() {
+        let wifi_status = WifiStatus::parse_iw_link(IW_LINK).unwrap();
+        assert_eq!(
+            wifi_status,
+            WifiStatus {
+                ssid: Some("Home Network".to_string()),
+                signal_dbm: Some(-62.0),
+                bitrate_mbps: Some(780.0),
+            }
+        );
+        assert_eq!(wifi_status.quality_percent(), Some(76));
+        assert_eq!(
+            wifi_status.i3blocks_print(&[
+                WifiDisplay::Ssid,
+                WifiDisplay::Signal,
+                WifiDisplay::Quality,
+                WifiDisplay::Bitrate
+            ]),
+            (
+                "Home Network -62dBm 76% 780Mbit/s".to_string(),
+                "-62dBm 76% 780Mbit/s".to_string()
+            )
+        );
+        assert_eq!(WifiStatus::parse_iw_link("Not connected.\n"), None);
+    }
+
+    #[test]
+    fn test_wifi_interfaces() {
+        let root = std::env::temp_dir().join(format!("wifi-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let proc_wireless = root.join("wireless");
+        fs::write(&proc_wireless, PROC_WIRELESS).unwrap();
+        assert_eq!(
+            WifiStatus::get_wireless_interfaces(&proc_wireless),
+            vec![
+                ("wlan0".to_string(), Some(-62.0)),
+                ("wlan1".to_string(), None)
+            ]
+        );
+
+        let iw_link = |interface: &str| {
+            Ok(Some(match interface {
+                "wlan0" => IW_LINK.to_string(),
+                _ => "Not connected.\n".to_string(),
+            }))
+        };
+        let no_iw = |interface: &str| WifiStatus::iw_link("/nonexistent/iw", interface);
+
+        let wifi_status = WifiStatus::new(&proc_wireless, None, iw_link).unwrap();
+        assert_eq!(
+            wifi_status.and_then(|x| x.ssid),
+            Some("Home Network".to_string())
+        );
+        assert_eq!(
+            WifiStatus::new(&proc_wireless, Some("wlan1"), iw_link).unwrap(),
+            None
+        );
+
+        // without iw, only the signal is known
+        let wifi_status = WifiStatus::new(&proc_wireless, None, no_iw).unwrap();
+        assert_eq!(
+            wifi_status,
+            Some(WifiStatus {
+                signal_dbm: Some(-62.0),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            WifiStatus::new(&proc_wireless, Some("wlan1"), no_iw).unwrap(),
+            None
+        );
+
+        // no wireless interface up
+        fs::write(
+            &proc_wireless,
+            PROC_WIRELESS.lines().take(2).collect::<Vec<_>>().join("\n"),
+        )
+        .unwrap();
+        assert_eq!(
+            WifiStatus::new(&proc_wireless, None, iw_link).unwrap(),
+            None
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,16 +1,29 @@
-use std::fmt::{self, Display};
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+};
 
 use clap::{Parser, Subcommand, ValueEnum};
 use cmds::{
+    cert_check::{CertCheck, CertCheckArgs},
     cpu::{CpuArgs, CpuStats},
     disk_io::{DiskIoArgs, DiskIoStats},
     disk_uage::{DiskStats, DiskUsageArgs},
+    dns_check::{DnsCheck, DnsCheckArgs},
+    http_check::{HttpCheck, HttpCheckArgs},
     icmp_check::{IcmpCheck, IcmpCheckArgs},
+    ip::{IpArgs, IpStatus},
     mem::{MemArgs, MemStats},
+    moonraker::{MoonrakerArgs, MoonrakerStatus},
     octoprint::{OctoprintArgs, OctoprintStatus},
     perfmode::{PerfModeArgs, PerformanceMode},
+    power::{PowerArgs, PowerStats},
+    printer::{PrinterArgs, PrinterStatus},
     prusa_link::{PrusaLinkArgs, PrusaLinkStatus},
+    systemd::{SystemdArgs, SystemdStatus},
     tcp_check::{TcpCheck, TcpCheckArgs},
+    vpn::{VpnArgs, VpnStatus},
+    wifi::{WifiArgs, WifiStatus},
 };
 mod cmds;
 
@@ -38,35 +51,56 @@ enum Commands {
     Mem(MemArgs),
     #[command(about = "Show Performance mode")]
     PerfMode(PerfModeArgs),
+    #[command(about = "Get power draw")]
+    Power(PowerArgs),
     #[command(about = "Get Disk IO info")]
     DiskIo(DiskIoArgs),
-    #[command(about = "Check hostname/ip with port availability")]
-    DiskUsage(DiskUsageArgs),
     #[command(about = "Check disk usage")]
+    DiskUsage(DiskUsageArgs),
+    #[command(about = "Check hostname/ip with port availability")]
     TcpCheck(TcpCheckArgs),
     #[command(about = "Check hostname/ip availability")]
     IcmpCheck(IcmpCheckArgs),
+    #[command(about = "Check HTTP(S) endpoint health")]
+    HttpCheck(HttpCheckArgs),
+    #[command(about = "Check TLS certificate expiry")]
+    CertCheck(CertCheckArgs),
+    #[command(about = "Check DNS resolution")]
+    DnsCheck(DnsCheckArgs),
     #[command(about = "Check octoprint job status")]
     Octoprint(OctoprintArgs),
     #[command(about = "Check PrusaLink job status")]
     PrusaLink(PrusaLinkArgs),
+    #[command(about = "Check Moonraker (Klipper) job status")]
+    Moonraker(MoonrakerArgs),
+    #[command(about = "Check 3D printer job status, for any backend")]
+    Printer(PrinterArgs),
+    #[command(about = "Count failed systemd units")]
+    Systemd(SystemdArgs),
+    #[command(about = "Get VPN tunnels status")]
+    Vpn(VpnArgs),
+    #[command(about = "Get Wi-Fi signal and SSID")]
+    Wifi(WifiArgs),
+    #[command(about = "Get local and public IP addresses")]
+    Ip(IpArgs),
 }
 
 #[derive(Clone, Copy, ValueEnum)]
 pub enum BytesUnit {
+    Auto,
     Kb,
     Mb,
     Gb,
+    Tb,
+    Pb,
 }
 
-impl fmt::Display for BytesUnit {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            BytesUnit::Kb => write!(f, "KB"),
-            BytesUnit::Mb => write!(f, "MB"),
-            BytesUnit::Gb => write!(f, "GB"),
-        }
-    }
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BytesStandard {
+    // powers of 1024: KiB, MiB, GiB...
+    Iec,
+    // powers of 1000: kB, MB, GB...
+    Si,
 }
 
 pub struct I3Display {
@@ -76,7 +110,8 @@ pub struct I3Display {
     pub color: Option<I3StatusRustColorState>,
 }
 
-#[derive(Clone)]
+// ordered by severity, so the worst state can be picked with `max`
+#[derive(Clone, PartialEq, Eq)]
 pub enum I3StatusRustColorState {
     I3StatusRustStateIdle,
     I3StatusRustStateInfo,
@@ -86,6 +121,38 @@ pub enum I3StatusRustColorState {
     HtmlColorCode(String),
 }
 
+impl I3StatusRustColorState {
+    /// HTML colors carry no severity, they rank below every state
+    fn severity(&self) -> u8 {
+        match self {
+            I3StatusRustColorState::HtmlColorCode(_) => 0,
+            I3StatusRustColorState::I3StatusRustStateIdle => 1,
+            I3StatusRustColorState::I3StatusRustStateInfo => 2,
+            I3StatusRustColorState::I3StatusRustStateGood => 3,
+            I3StatusRustColorState::I3StatusRustStateWarning => 4,
+            I3StatusRustColorState::I3StatusRustStateCritical => 5,
+        }
+    }
+}
+
+impl Ord for I3StatusRustColorState {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (
+                I3StatusRustColorState::HtmlColorCode(x),
+                I3StatusRustColorState::HtmlColorCode(y),
+            ) => x.cmp(y),
+            _ => self.severity().cmp(&other.severity()),
+        }
+    }
+}
+
+impl PartialOrd for I3StatusRustColorState {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Display for I3StatusRustColorState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -137,24 +204,43 @@ impl I3Display {
                     println!("{color}");
                 }
             }
-            OutputType::I3StatusRust => {
-                // note: I do not use serde to avoid useless resources usage and reduce binary size
-                let jsonify = |key, val| format!("\"{}\":\"{}\"", key, val);
-                let mut output_content = Vec::with_capacity(4);
-
-                output_content.push(jsonify("text", &self.long_line));
-                output_content.push(jsonify("short_text", &self.short_line));
-                if let Some(icon) = &self.icon {
-                    output_content.push(jsonify("icon", icon));
-                }
-                if let Some(color) = &self.color {
-                    output_content.push(jsonify("state", &color.to_string()));
-                }
+            OutputType::I3StatusRust => println!("{}", self.to_json()),
+        }
+    }
 
-                println!("{{{}}}", output_content.join(","))
-            }
+    fn to_json(&self) -> String {
+        // note: I do not use serde to avoid useless resources usage and reduce binary size
+        let jsonify = |key, val: &str| format!("\"{}\":\"{}\"", key, json_escape(val));
+        let mut output_content = Vec::with_capacity(4);
+
+        output_content.push(jsonify("text", &self.long_line));
+        output_content.push(jsonify("short_text", &self.short_line));
+        if let Some(icon) = &self.icon {
+            output_content.push(jsonify("icon", icon));
+        }
+        if let Some(color) = &self.color {
+            output_content.push(jsonify("state", &color.to_string()));
+        }
+
+        format!("{{{}}}", output_content.join(","))
+    }
+}
+
+/// Escape a JSON string content, values like SSIDs or file names can hold anything
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
         }
     }
+    escaped
 }
 
 trait CommandStatus<T> {
@@ -169,11 +255,21 @@ fn main() {
         Commands::Mem(x) => MemStats::get(x),
         Commands::DiskIo(x) => DiskIoStats::get(x),
         Commands::PerfMode(x) => PerformanceMode::get(x),
+        Commands::Power(x) => PowerStats::get(x),
         Commands::TcpCheck(x) => TcpCheck::get(x),
         Commands::IcmpCheck(x) => IcmpCheck::get(x),
+        Commands::HttpCheck(x) => HttpCheck::get(x),
+        Commands::CertCheck(x) => CertCheck::get(x),
+        Commands::DnsCheck(x) => DnsCheck::get(x),
         Commands::DiskUsage(x) => DiskStats::get(x),
         Commands::Octoprint(x) => OctoprintStatus::get(x),
         Commands::PrusaLink(x) => PrusaLinkStatus::get(x),
+        Commands::Moonraker(x) => MoonrakerStatus::get(x),
+        Commands::Printer(x) => PrinterStatus::get(x),
+        Commands::Systemd(x) => SystemdStatus::get(x),
+        Commands::Vpn(x) => VpnStatus::get(x),
+        Commands::Wifi(x) => WifiStatus::get(x),
+        Commands::Ip(x) => IpStatus::get(x),
     };
 
     match res {
@@ -190,3 +286,41 @@ fn main() {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{I3Display, I3StatusRustColorState};
+
+    #[test]
+    fn test_i3status_rust_json() {
+        let display = I3Display::new(
+            None,
+            "Cafe \"Wi-Fi\" \\o/\u{1}".to_string(),
+            "short\tline\n".to_string(),
+            Some(I3StatusRustColorState::I3StatusRustStateGood),
+        );
+        let json = display.to_json();
+        assert_eq!(
+            json,
+            r#"{"text":"Cafe \"Wi-Fi\" \\o/\u0001","short_text":"short\tline\n","state":"Good"}"#
+        );
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(value["text"], "Cafe \"Wi-Fi\" \\o/\u{1}");
+    }
+
+    #[test]
+    fn test_color_state_severity() {
+        let worst = [
+            I3StatusRustColorState::HtmlColorCode("#ff0000".to_string()),
+            I3StatusRustColorState::I3StatusRustStateWarning,
+            I3StatusRustColorState::I3StatusRustStateGood,
+        ]
+        .into_iter()
+        .max();
+        assert!(worst == Some(I3StatusRustColorState::I3StatusRustStateWarning));
+        assert!(
+            I3StatusRustColorState::HtmlColorCode("#ff0000".to_string())
+                < I3StatusRustColorState::I3StatusRustStateIdle
+        );
+    }
+}